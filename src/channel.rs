@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// Transport of the launcher messages to the game. The launcher uses the shared memory and
+/// semaphores of its LauncherContext, tests record the messages in memory instead. Off Windows
+/// the Win32 parts of the crate are left out, so the handshake tests also run on Linux hosts.
+pub trait GameChannel {
+    /// Writes a message where the game reads it, replacing the previous one
+    fn write_message(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Lets the game read the written message and waits until it did
+    fn wait_for_game(&mut self, timeout: Duration) -> Result<()>;
+
+    /// Writes a message and waits until the game read it
+    fn send(&mut self, bytes: &[u8], timeout: Duration) -> Result<()> {
+        self.write_message(bytes)?;
+        self.wait_for_game(timeout)
+    }
+}
+
+/// Keeps a copy of every message written through the wrapped channel
+pub struct RecordingChannel<'a> {
+    channel: &'a mut dyn GameChannel,
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl<'a> RecordingChannel<'a> {
+    pub fn new(channel: &'a mut dyn GameChannel) -> Self {
+        Self {
+            channel,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl GameChannel for RecordingChannel<'_> {
+    fn write_message(&mut self, bytes: &[u8]) -> Result<()> {
+        self.channel.write_message(bytes)?;
+        self.messages.push(bytes.to_vec());
+        Ok(())
+    }
+
+    fn wait_for_game(&mut self, timeout: Duration) -> Result<()> {
+        self.channel.wait_for_game(timeout)
+    }
+
+    fn send(&mut self, bytes: &[u8], timeout: Duration) -> Result<()> {
+        self.channel.send(bytes, timeout)?;
+        self.messages.push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Stands in for the game, every message written is read right away
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockGameChannel {
+    pub messages: Vec<Vec<u8>>,
+    written_message: Option<Vec<u8>>,
+}
+
+#[cfg(test)]
+impl GameChannel for MockGameChannel {
+    fn write_message(&mut self, bytes: &[u8]) -> Result<()> {
+        self.written_message = Some(bytes.to_vec());
+        Ok(())
+    }
+
+    fn wait_for_game(&mut self, _timeout: Duration) -> Result<()> {
+        let message = self.written_message.take().ok_or(anyhow::anyhow!(
+            "Waiting for the game without writing a message first"
+        ))?;
+        self.messages.push(message);
+        Ok(())
+    }
+}
@@ -1,10 +1,272 @@
+use std::str::FromStr;
+
 use anyhow::Result;
-use windows::Win32::Graphics::Gdi::{EnumDisplaySettingsA, DEVMODEA, ENUM_CURRENT_SETTINGS};
+use log::LevelFilter;
+#[cfg(windows)]
+use windows::{
+    core::PCSTR,
+    Win32::Graphics::Gdi::{
+        EnumDisplayDevicesA, EnumDisplaySettingsA, DEVMODEA, DISPLAY_DEVICEA,
+        DISPLAY_DEVICE_ATTACHED_TO_DESKTOP, ENUM_CURRENT_SETTINGS, ENUM_DISPLAY_SETTINGS_MODE,
+    },
+};
+
+use crate::{
+    logging::{LogFormat, DEFAULT_LOG_MAX_FILES},
+    GameType, StoreType,
+};
+
+const MAX_VOLUME: i32 = 100;
+const MAX_REFRESH_RATE: u32 = 1000;
+
+const DEFAULT_CONFIG_FILE: &str = r#"# FF78Launcher configuration
+
+# Launch the game in fullscreen mode
+fullscreen = false
+# Display mode of the game (windowed, fullscreen or borderless), takes precedence over fullscreen
+# display_mode = "borderless"
+# Use the desktop resolution in fullscreen or borderless when no resolution is set, disable it to fall back to 640x480
+fullscreen_use_desktop_resolution = true
+# Window resolution, 0x0 uses the current display resolution in fullscreen or 640x480 in window mode
+window_width = 0
+window_height = 0
+# Monitor used to detect the fullscreen resolution, 0 is the first one, defaults to the primary one
+# monitor_index = 1
+# Refresh rate in Hz, "auto" (or 0) uses the current display refresh rate in fullscreen or 60 in window mode
+refresh_rate = "auto"
+# Smooth the game image when it gets scaled
+enable_linear_filtering = false
+# Keep the original 4:3 aspect ratio
+keep_aspect_ratio = false
+# Use the original game graphics mode
+original_mode = false
+# Pause the game when its window loses focus (FF8, or FF7 with FFNx)
+pause_game_on_background = false
+# Sound effects volume (0-100)
+sfx_volume = 100
+# Music volume (0-100)
+music_volume = 100
+# Launch Chocobo World instead of the game (FF8 only)
+launch_chocobo = false
+# Path of the game executable to launch instead of the detected one
+# game_executable = "ff7_en.exe"
+# Game to launch when both FF7 and FF8 executables are found (ff7 or ff8), the --game flag takes precedence
+# preferred_game = "ff7"
+# Seconds to wait for the game to read each launcher message
+handshake_timeout_secs = 30
+# Prefix of the shared memory and semaphore names, for game builds not using ff7, ff8 or choco
+# semaphore_prefix = "ff7"
+# Order of the launcher messages, steps can be moved or left out to debug a game build but
+# launcher_completed must stay last
+# send_order = ["locale_data_dir", "user_save_dir", "user_doc_dir", "install_dir", "game_version", "disable_cloud", "bg_pause_enabled", "launcher_completed"]
+# Milliseconds to wait after starting the game before sending the first message, for slow starting systems
+startup_delay_ms = 0
+# Startup delay used instead when running under Wine or Proton and it is longer, their semaphores can be slower to come up
+wine_startup_delay_ms = 0
+# Log verbosity (off, error, warn, info, debug, trace), the FF78_LOG environment variable takes precedence
+log_level = "info"
+# Set the SteamAppId and SteamGameId environment variables of the game, like Steam does on the games
+# it starts, and check that Steam is installed. The game exe is still started directly by the launcher,
+# `steam -applaunch` would only start the official launcher again
+launch_via_steam = false
+# Start the game window minimized, for tools that only need the game process running
+start_minimized = false
+# Start the game window hidden, takes precedence over start_minimized
+start_hidden = false
+# Debugger (e.g. x32dbg or WinDbg) attached with -p <pid> to the game started suspended, so it sees
+# the game from its first instruction. Raise handshake_timeout_secs when stepping through the startup
+# debugger = "C:\\x64dbg\\release\\x32\\x32dbg.exe"
+# Steam app id the game is started as with launch_via_steam, defaults to the detected game one
+# steam_app_id = 39140
+# Write the video cfg file from this config, disable it to keep your own edited file as is
+manage_video_cfg = true
+# Also write the FF7 video cfg when FFNx is used, it is the only way to set the background pause flag
+# of FF7. Off by default as FFNx launches otherwise leave the file untouched
+manage_video_cfg_with_ffnx = false
+# Write the sound cfg file from this config, disable it to keep your own edited file as is
+manage_sound_cfg = true
+# Input cfg file copied over the game one before launching, to switch between saved control schemes
+# input_cfg = "C:\\Controls\\ff7input_gamepad.cfg"
+# Absolute path of the save directory sent to the game instead of the detected one
+# save_dir = "C:\\Saves\\FF7"
+# Save profile (user_* folder of the metadata directory) to use when several accounts have one, defaults to the last one
+# save_profile = "user_12345678"
+# Commands run through cmd before the game starts and after it exits
+# pre_launch_command = "patcher.exe"
+# post_launch_command = "backup_saves.bat"
+# Command run through cmd once the game started, {pid} is replaced by the game process id
+# on_launch_command = "injector.exe --pid {pid}"
+# Keep launching even if the pre or post launch command fails
+continue_on_hook_error = false
+# Copy the save directory into save_backups before launching the game
+backup_saves = false
+# Number of save backups to keep, the oldest ones are deleted
+max_save_backups = 10
+# Documents folder used instead of the detected one, "Square Enix\\FINAL FANTASY VII Steam" (or VIII) is appended
+# unless the path already points to it
+# metadata_dir = "D:\\Documents"
+# Position of the game window on the desktop, only applied in window mode
+# window_x = 0
+# window_y = 0
+# Force the FFNx launch path on (true) or off (false), by default it is detected from the AF3DN.P size
+# force_ffnx = true
+# Show a message box with the exit code when the game exits abnormally
+warn_on_crash = false
+# Copy the launcher log, FFNx.log and the game crash dump into crash_report/<timestamp> when the game exits abnormally
+collect_crash_report = false
+# Send the install dir registered by Steam instead of the current directory
+use_registry_install_dir = false
+# Language sent to the game instead of the executable one (de, en, es, fr, it or ja), the executable still
+# decides the process to run. locale_override is accepted as an older name of this key
+# language = "ja"
+# Terminate an already running game instance instead of asking whether to abort the launch
+kill_existing_instance = false
+# Path of the log file, defaults to FF78Launcher.log in the current directory
+# log_path = "C:\\Logs\\FF78Launcher.log"
+# Size in bytes above which the log file is rotated to <log_path>.1, the log of the previous
+# launch is rotated at startup too. 0 never rotates it, each launch appends to it
+log_max_bytes = 0
+# Number of rotated log files kept, the oldest one is deleted
+log_max_files = 3
+# Log file format (plain or json), json writes one object per line for tooling
+log_format = "plain"
+# Also report errors, warnings and the game launch and exit to the Windows Application Event Log
+log_to_event_log = false
+# Working directory of the game process, defaults to the directory of the game executable
+# working_dir = "C:\\Games\\FF7"
+# Store type of the game (standard for Steam or estore) selecting its message ids and metadata directory,
+# detected from the AF3DN.P size and the data/music_2 folder when unset
+# store_type = "estore"
+# Arguments passed to the game executable, the ones after -- on the command line are appended
+# game_args = ["-debug"]
+# Clear the shared memory left over by a crashed previous session before using it, always done when
+# the previous launcher session ended without removing its lock file
+reset_stale_shared_memory = true
+# CPU cores the game runs on, as a bit mask integer or 0x prefixed hex string (e.g. "0x3" for the first two cores)
+# affinity_mask = "0x3"
+# Priority of the game process (normal, above_normal or high), left untouched when unset
+# process_priority = "above_normal"
+# Steam user id naming the user_<id> save directory created when none exists yet, defaults to the
+# user logged in the Steam client, "save" is used as directory when none is found
+# steam_user_id = 12345678
+# Show a dialog when launching fails, disable it to only log the error and exit with a nonzero code
+show_error_dialog = true
+# Catch launcher crashes to log them and write a crash dump, disable it (or pass --no-exception-handler)
+# to let an attached debugger or Windows Error Reporting receive the crash instead. Crash dumps may
+# contain personal data such as paths and user names, keep them private when sharing logs
+install_exception_handler = true
+# Seconds to wait for the game to open its window before warning about it in the log, 0 disables the watchdog
+window_watchdog_secs = 0
+# Also show a message box when the watchdog finds no game window
+window_watchdog_dialog = false
+# Show a tray icon while the game runs, its right click menu kills a hung game or opens the log
+show_tray_icon = false
+# Show a small status window while the launcher prepares the game, closed once the game window opens
+show_splash = false
+
+# Per-game overrides taking precedence over the root value, any key above can be set here except
+# game_executable, preferred_game, show_error_dialog and show_splash, which are read before the game is known
+# [ff7]
+# window_width = 1280
+# window_height = 720
+
+# [ff8]
+# music_volume = 80
 
-use crate::GameType;
+# Environment variables set for the game process, e.g. read by FFNx or mods
+# [env]
+# MY_MOD_LOG = "1"
+
+# Payloads sent instead of the computed ones, keyed by message id, for protocol experiments. Only
+# string messages (directories, language, version and Steam user id) can be overridden
+# [overrides]
+# 18 = "FF78Launcher 0.0.1"
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    Windowed,
+    Fullscreen,
+    Borderless,
+}
+
+impl FromStr for DisplayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "windowed" => Ok(DisplayMode::Windowed),
+            "fullscreen" => Ok(DisplayMode::Fullscreen),
+            "borderless" => Ok(DisplayMode::Borderless),
+            _ => Err(anyhow::anyhow!("Invalid display mode {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessPriority {
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl FromStr for ProcessPriority {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "normal" => Ok(ProcessPriority::Normal),
+            "above_normal" => Ok(ProcessPriority::AboveNormal),
+            "high" => Ok(ProcessPriority::High),
+            _ => Err(anyhow::anyhow!("Invalid process priority {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStep {
+    LocaleDataDir,
+    UserSaveDir,
+    UserDocDir,
+    InstallDir,
+    GameVersion,
+    DisableCloud,
+    BgPauseEnabled,
+    LauncherCompleted,
+}
+
+pub const DEFAULT_SEND_ORDER: [HandshakeStep; 8] = [
+    HandshakeStep::LocaleDataDir,
+    HandshakeStep::UserSaveDir,
+    HandshakeStep::UserDocDir,
+    HandshakeStep::InstallDir,
+    HandshakeStep::GameVersion,
+    HandshakeStep::DisableCloud,
+    HandshakeStep::BgPauseEnabled,
+    HandshakeStep::LauncherCompleted,
+];
+
+impl FromStr for HandshakeStep {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "locale_data_dir" => Ok(HandshakeStep::LocaleDataDir),
+            "user_save_dir" => Ok(HandshakeStep::UserSaveDir),
+            "user_doc_dir" => Ok(HandshakeStep::UserDocDir),
+            "install_dir" => Ok(HandshakeStep::InstallDir),
+            "game_version" => Ok(HandshakeStep::GameVersion),
+            "disable_cloud" => Ok(HandshakeStep::DisableCloud),
+            "bg_pause_enabled" => Ok(HandshakeStep::BgPauseEnabled),
+            "launcher_completed" => Ok(HandshakeStep::LauncherCompleted),
+            _ => Err(anyhow::anyhow!("Invalid handshake step {}", value)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Config {
+    pub display_mode: DisplayMode,
     pub fullscreen: bool,
     pub window_width: u32,
     pub window_height: u32,
@@ -16,11 +278,62 @@ pub struct Config {
     pub sfx_volume: i32,
     pub music_volume: i32,
     pub launch_chocobo: bool,
+    pub handshake_timeout_secs: u32,
+    pub send_order: Vec<HandshakeStep>,
+    pub semaphore_prefix: Option<String>,
+    pub log_level: LevelFilter,
+    pub launch_via_steam: bool,
+    pub steam_app_id: Option<u32>,
+    pub manage_video_cfg: bool,
+    pub manage_video_cfg_with_ffnx: bool,
+    pub manage_sound_cfg: bool,
+    pub input_cfg: Option<String>,
+    pub save_dir: Option<String>,
+    pub pre_launch_command: Option<String>,
+    pub post_launch_command: Option<String>,
+    pub on_launch_command: Option<String>,
+    pub continue_on_hook_error: bool,
+    pub backup_saves: bool,
+    pub max_save_backups: u32,
+    pub metadata_dir: Option<String>,
+    pub window_x: Option<i32>,
+    pub window_y: Option<i32>,
+    pub force_ffnx: Option<bool>,
+    pub warn_on_crash: bool,
+    pub collect_crash_report: bool,
+    pub use_registry_install_dir: bool,
+    pub locale_override: Option<String>,
+    pub kill_existing_instance: bool,
+    pub log_format: LogFormat,
+    pub working_dir: Option<String>,
+    pub store_type: Option<StoreType>,
+    pub env: Vec<(String, String)>,
+    pub game_args: Vec<String>,
+    pub reset_stale_shared_memory: bool,
+    pub affinity_mask: Option<u64>,
+    pub process_priority: Option<ProcessPriority>,
+    pub steam_user_id: Option<u64>,
+    pub startup_delay_ms: u32,
+    pub wine_startup_delay_ms: u32,
+    pub log_to_event_log: bool,
+    pub install_exception_handler: bool,
+    pub save_profile: Option<String>,
+    pub show_tray_icon: bool,
+    pub payload_overrides: Vec<(u32, String)>,
+    pub window_watchdog_secs: u32,
+    pub window_watchdog_dialog: bool,
+    pub log_path: Option<String>,
+    pub log_max_bytes: u64,
+    pub log_max_files: u32,
+    pub start_minimized: bool,
+    pub start_hidden: bool,
+    pub debugger: Option<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            display_mode: DisplayMode::Windowed,
             fullscreen: Default::default(),
             window_width: Default::default(),
             window_height: Default::default(),
@@ -32,20 +345,129 @@ impl Default for Config {
             sfx_volume: 100,
             music_volume: 100,
             launch_chocobo: Default::default(),
+            handshake_timeout_secs: 30,
+            send_order: DEFAULT_SEND_ORDER.to_vec(),
+            semaphore_prefix: Default::default(),
+            log_level: LevelFilter::Info,
+            launch_via_steam: Default::default(),
+            steam_app_id: Default::default(),
+            manage_video_cfg: true,
+            manage_video_cfg_with_ffnx: Default::default(),
+            manage_sound_cfg: true,
+            input_cfg: Default::default(),
+            save_dir: Default::default(),
+            pre_launch_command: Default::default(),
+            post_launch_command: Default::default(),
+            on_launch_command: Default::default(),
+            continue_on_hook_error: Default::default(),
+            backup_saves: Default::default(),
+            max_save_backups: 10,
+            metadata_dir: Default::default(),
+            window_x: Default::default(),
+            window_y: Default::default(),
+            force_ffnx: Default::default(),
+            warn_on_crash: Default::default(),
+            collect_crash_report: Default::default(),
+            use_registry_install_dir: Default::default(),
+            locale_override: Default::default(),
+            kill_existing_instance: Default::default(),
+            log_format: Default::default(),
+            working_dir: Default::default(),
+            store_type: Default::default(),
+            env: Default::default(),
+            game_args: Default::default(),
+            reset_stale_shared_memory: true,
+            affinity_mask: Default::default(),
+            process_priority: Default::default(),
+            steam_user_id: Default::default(),
+            startup_delay_ms: Default::default(),
+            wine_startup_delay_ms: Default::default(),
+            log_to_event_log: Default::default(),
+            install_exception_handler: true,
+            save_profile: Default::default(),
+            show_tray_icon: Default::default(),
+            payload_overrides: Default::default(),
+            window_watchdog_secs: Default::default(),
+            window_watchdog_dialog: Default::default(),
+            log_path: Default::default(),
+            log_max_bytes: Default::default(),
+            log_max_files: DEFAULT_LOG_MAX_FILES,
+            start_minimized: Default::default(),
+            start_hidden: Default::default(),
+            debugger: Default::default(),
         }
     }
 }
 
 impl Config {
-    pub fn from_config_file(path: &str, game_type: &GameType) -> Result<Self> {
+    pub fn read_config_table(path: &str) -> Result<toml::Table> {
+        if !std::fs::exists(path).is_ok_and(|exists| exists) {
+            match write_default_config_file(path) {
+                Ok(_) => log::info!("Default config file written to {path}"),
+                Err(err) => log::warn!("Default config file could not be written: {:?}", err),
+            }
+        }
+
         let file_contents = std::fs::read(path);
         let file_contents = file_contents.unwrap_or_default();
         let table: toml::Table = toml::from_str(std::str::from_utf8(&file_contents)?)?;
+        Ok(table)
+    }
+
+    pub fn game_executable(table: &toml::Table) -> Option<String> {
+        table
+            .get("game_executable")
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    }
+
+    pub fn preferred_game(table: &toml::Table) -> Option<String> {
+        table
+            .get("preferred_game")
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    }
+
+    pub fn show_error_dialog(table: &toml::Table) -> bool {
+        table
+            .get("show_error_dialog")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true)
+    }
 
-        let fullscreen = table
-            .get("fullscreen")
+    pub fn show_splash(table: &toml::Table) -> bool {
+        table
+            .get("show_splash")
             .and_then(|value| value.as_bool())
-            .unwrap_or(false);
+            .unwrap_or(false)
+    }
+
+    /// Values of the game section (`[ff7]` or `[ff8]`) take precedence over the root ones,
+    /// which in turn take precedence over the defaults
+    pub fn from_config_table(table: &toml::Table, game_type: &GameType) -> Result<Self> {
+        let table = &merge_game_section(table, game_type);
+
+        // fullscreen = true is kept as an alias of display_mode = "fullscreen"
+        let display_mode = table
+            .get("display_mode")
+            .and_then(|value| value.as_str())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| {
+                match table
+                    .get("fullscreen")
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or(false)
+                {
+                    true => DisplayMode::Fullscreen,
+                    false => DisplayMode::Windowed,
+                }
+            });
+        let fullscreen = display_mode == DisplayMode::Fullscreen;
+        let desktop_sized = display_mode != DisplayMode::Windowed;
+        let fullscreen_use_desktop_resolution = table
+            .get("fullscreen_use_desktop_resolution")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true);
         let mut window_width = table
             .get("window_width")
             .and_then(|value| value.as_integer())
@@ -58,45 +480,74 @@ impl Config {
             .max(0) as u32;
         let mut refresh_rate = table
             .get("refresh_rate")
+            .map(parse_refresh_rate)
+            .unwrap_or(0);
+
+        let requested_refresh_rate = refresh_rate;
+
+        let monitor_index = table
+            .get("monitor_index")
             .and_then(|value| value.as_integer())
-            .unwrap_or(0)
-            .max(0) as u32;
+            .map(|value| value.max(0) as u32);
+        let display_device = monitor_index.and_then(display_device_name);
 
         if window_width == 0 && window_height == 0 {
-            let mut display_settings = DEVMODEA::default();
-            let display_settings_found = unsafe {
-                EnumDisplaySettingsA(None, ENUM_CURRENT_SETTINGS, &mut display_settings).as_bool()
-            };
-            log::info!("Display settings found: {}x{} (refresh rate: {})", display_settings.dmPelsWidth, display_settings.dmPelsHeight, display_settings.dmDisplayFrequency);
-            if fullscreen && display_settings_found {
-                window_width = display_settings.dmPelsWidth;
-                window_height = display_settings.dmPelsHeight;
-                if refresh_rate == 0 {
-                    refresh_rate = display_settings.dmDisplayFrequency;
+            let display_settings = current_display_settings(display_device.as_ref());
+            if let Some(display_settings) = &display_settings {
+                log::info!(
+                    "Display settings found: {}x{} (refresh rate: {})",
+                    display_settings.width,
+                    display_settings.height,
+                    display_settings.refresh_rate
+                );
+            } else if desktop_sized {
+                log::warn!("Desktop resolution could not be read, falling back to 640x480");
+            }
+            let desktop_settings = display_settings.filter(|_| desktop_sized);
+            match desktop_settings
+                .as_ref()
+                .filter(|_| fullscreen_use_desktop_resolution)
+            {
+                Some(desktop_settings) => {
+                    window_width = desktop_settings.width;
+                    window_height = desktop_settings.height;
                 }
-            } else {
-                window_width = 640;
-                window_height = 480;
-                if refresh_rate == 0 {
-                    refresh_rate = 60;
+                None => {
+                    window_width = 640;
+                    window_height = 480;
                 }
             }
+            if refresh_rate == 0 {
+                refresh_rate =
+                    desktop_settings.map_or(60, |desktop_settings| desktop_settings.refresh_rate);
+            }
+        } else if window_width == 0 || window_height == 0 {
+            let (aspect_width, aspect_height) = current_display_settings(display_device.as_ref())
+                .filter(|display_settings| {
+                    display_settings.width > 0 && display_settings.height > 0
+                })
+                .map_or((4, 3), |display_settings| {
+                    (display_settings.width, display_settings.height)
+                });
+            if window_height == 0 {
+                window_height = window_width * aspect_height / aspect_width;
+            } else {
+                window_width = window_height * aspect_width / aspect_height;
+            }
         }
+        log::info!("Resolution resolved: {window_width}x{window_height}");
 
-        let mut pause_game_on_background = table
-            .get("pause_game_on_background")
-            .and_then(|value| value.as_bool())
-            .unwrap_or(false);
-        let mut launch_chocobo = table
-            .get("launch_chocobo")
-            .and_then(|value| value.as_bool())
-            .unwrap_or(false);
-        if let GameType::FF7(_) = game_type {
-            pause_game_on_background = false;
-            launch_chocobo = false;
+        if requested_refresh_rate != 0 {
+            refresh_rate = validate_refresh_rate(
+                display_device.as_ref(),
+                window_width,
+                window_height,
+                requested_refresh_rate,
+            );
         }
 
-        Ok(Config {
+        let mut config = Config {
+            display_mode,
             fullscreen,
             window_width,
             window_height,
@@ -113,18 +564,767 @@ impl Config {
                 .get("original_mode")
                 .and_then(|value| value.as_bool())
                 .unwrap_or(false),
-            pause_game_on_background,
-            sfx_volume: table
-                .get("sfx_volume")
+            pause_game_on_background: table
+                .get("pause_game_on_background")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            sfx_volume: parse_volume(table, "sfx_volume"),
+            music_volume: parse_volume(table, "music_volume"),
+            launch_chocobo: table
+                .get("launch_chocobo")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            handshake_timeout_secs: table
+                .get("handshake_timeout_secs")
+                .and_then(|value| value.as_integer())
+                .unwrap_or(30)
+                .max(1) as u32,
+            send_order: table
+                .get("send_order")
+                .and_then(|value| value.as_array())
+                .map(|send_order| parse_send_order(send_order))
+                .unwrap_or(DEFAULT_SEND_ORDER.to_vec()),
+            semaphore_prefix: table
+                .get("semaphore_prefix")
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string()),
+            log_level: table
+                .get("log_level")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(LevelFilter::Info),
+            launch_via_steam: table
+                .get("launch_via_steam")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            steam_app_id: table
+                .get("steam_app_id")
+                .and_then(|value| value.as_integer())
+                .map(|value| value.max(0) as u32),
+            manage_video_cfg: table
+                .get("manage_video_cfg")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            manage_video_cfg_with_ffnx: table
+                .get("manage_video_cfg_with_ffnx")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            manage_sound_cfg: table
+                .get("manage_sound_cfg")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            input_cfg: table
+                .get("input_cfg")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            save_dir: table
+                .get("save_dir")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            pre_launch_command: table
+                .get("pre_launch_command")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            post_launch_command: table
+                .get("post_launch_command")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            on_launch_command: table
+                .get("on_launch_command")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            continue_on_hook_error: table
+                .get("continue_on_hook_error")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            backup_saves: table
+                .get("backup_saves")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            max_save_backups: table
+                .get("max_save_backups")
+                .and_then(|value| value.as_integer())
+                .unwrap_or(10)
+                .max(1) as u32,
+            metadata_dir: table
+                .get("metadata_dir")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            window_x: table
+                .get("window_x")
+                .and_then(|value| value.as_integer())
+                .map(|value| value as i32),
+            window_y: table
+                .get("window_y")
+                .and_then(|value| value.as_integer())
+                .map(|value| value as i32),
+            force_ffnx: table.get("force_ffnx").and_then(|value| value.as_bool()),
+            warn_on_crash: table
+                .get("warn_on_crash")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            collect_crash_report: table
+                .get("collect_crash_report")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            use_registry_install_dir: table
+                .get("use_registry_install_dir")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            locale_override: table
+                .get("language")
+                .or_else(|| table.get("locale_override"))
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_lowercase()),
+            kill_existing_instance: table
+                .get("kill_existing_instance")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            log_format: table
+                .get("log_format")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default(),
+            working_dir: table
+                .get("working_dir")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            store_type: table
+                .get("store_type")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok()),
+            env: table
+                .get("env")
+                .and_then(|value| value.as_table())
+                .map(|env| {
+                    env.iter()
+                        .map(|(key, value)| match value {
+                            toml::Value::String(value) => (key.clone(), value.clone()),
+                            value => (key.clone(), value.to_string()),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            game_args: table
+                .get("game_args")
+                .and_then(|value| value.as_array())
+                .map(|game_args| {
+                    game_args
+                        .iter()
+                        .filter_map(|value| value.as_str().map(|value| value.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            reset_stale_shared_memory: table
+                .get("reset_stale_shared_memory")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            affinity_mask: table.get("affinity_mask").and_then(parse_affinity_mask),
+            process_priority: table
+                .get("process_priority")
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok()),
+            steam_user_id: table
+                .get("steam_user_id")
+                .and_then(|value| value.as_integer())
+                .map(|value| value.max(0) as u64),
+            startup_delay_ms: table
+                .get("startup_delay_ms")
+                .and_then(|value| value.as_integer())
+                .unwrap_or(0)
+                .clamp(0, u32::MAX as i64) as u32,
+            wine_startup_delay_ms: table
+                .get("wine_startup_delay_ms")
+                .and_then(|value| value.as_integer())
+                .unwrap_or(0)
+                .clamp(0, u32::MAX as i64) as u32,
+            log_to_event_log: table
+                .get("log_to_event_log")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            install_exception_handler: table
+                .get("install_exception_handler")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            save_profile: table
+                .get("save_profile")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            show_tray_icon: table
+                .get("show_tray_icon")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            payload_overrides: table
+                .get("overrides")
+                .and_then(|value| value.as_table())
+                .map(parse_payload_overrides)
+                .unwrap_or_default(),
+            window_watchdog_secs: table
+                .get("window_watchdog_secs")
                 .and_then(|value| value.as_integer())
                 .unwrap_or(0)
-                .max(0) as i32,
-            music_volume: table
-                .get("music_volume")
+                .clamp(0, u32::MAX as i64) as u32,
+            window_watchdog_dialog: table
+                .get("window_watchdog_dialog")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            log_path: table
+                .get("log_path")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+            log_max_bytes: table
+                .get("log_max_bytes")
                 .and_then(|value| value.as_integer())
                 .unwrap_or(0)
-                .max(0) as i32,
-            launch_chocobo,
+                .max(0) as u64,
+            log_max_files: table
+                .get("log_max_files")
+                .and_then(|value| value.as_integer())
+                .unwrap_or(DEFAULT_LOG_MAX_FILES as i64)
+                .clamp(0, u32::MAX as i64) as u32,
+            start_minimized: table
+                .get("start_minimized")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            start_hidden: table
+                .get("start_hidden")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            debugger: table
+                .get("debugger")
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string()),
+        };
+        if let GameType::FF7(_) = game_type {
+            config.launch_chocobo = false;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the invariants the parsing cannot restore by itself. Volumes are clamped and
+    /// launch_chocobo is turned off for FF7 while parsing, so they always hold here.
+    pub fn validate(&self) -> Result<()> {
+        let mut violations = Vec::new();
+        if self.window_width == 0 || self.window_height == 0 {
+            violations.push(format!(
+                "resolution {}x{} has a zero dimension",
+                self.window_width, self.window_height
+            ));
+        }
+        if self.refresh_rate > MAX_REFRESH_RATE {
+            violations.push(format!(
+                "refresh_rate {} is above {MAX_REFRESH_RATE}",
+                self.refresh_rate
+            ));
+        }
+        if self.send_order.last() != Some(&HandshakeStep::LauncherCompleted)
+            || self
+                .send_order
+                .iter()
+                .filter(|step| **step == HandshakeStep::LauncherCompleted)
+                .count()
+                != 1
+        {
+            violations
+                .push("send_order must end with its only launcher_completed step".to_string());
+        }
+
+        if !violations.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid configuration: {}",
+                violations.join("; ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Current mode of a display
+struct DisplaySettings {
+    width: u32,
+    height: u32,
+    refresh_rate: u32,
+}
+
+/// Name of the monitor attached to the desktop at the given index, None falls back to the primary one
+#[cfg(windows)]
+fn display_device_name(monitor_index: u32) -> Option<[u8; 32]> {
+    let mut device_index = 0;
+    let mut monitor_count = 0;
+    loop {
+        let mut display_device = DISPLAY_DEVICEA {
+            cb: std::mem::size_of::<DISPLAY_DEVICEA>() as u32,
+            ..Default::default()
+        };
+        let display_device_found =
+            unsafe { EnumDisplayDevicesA(None, device_index, &mut display_device, 0).as_bool() };
+        if !display_device_found {
+            break;
+        }
+        if display_device.StateFlags & DISPLAY_DEVICE_ATTACHED_TO_DESKTOP != 0 {
+            if monitor_count == monitor_index {
+                // The device name is a null terminated CHAR array
+                let device_name = display_device.DeviceName.map(|c| c as u8);
+                log::info!(
+                    "Using monitor {monitor_index}: {}",
+                    String::from_utf8_lossy(&device_name).trim_end_matches('\0')
+                );
+                return Some(device_name);
+            }
+            monitor_count += 1;
+        }
+        device_index += 1;
+    }
+    log::warn!("monitor_index = {monitor_index} not found ({monitor_count} monitors), using the primary one");
+    None
+}
+
+#[cfg(not(windows))]
+fn display_device_name(_monitor_index: u32) -> Option<[u8; 32]> {
+    None
+}
+
+/// Current settings of the display device, the primary one when None
+#[cfg(windows)]
+fn current_display_settings(display_device: Option<&[u8; 32]>) -> Option<DisplaySettings> {
+    let mut display_settings = DEVMODEA::default();
+    let display_settings_found = unsafe {
+        EnumDisplaySettingsA(
+            display_device_pcstr(display_device),
+            ENUM_CURRENT_SETTINGS,
+            &mut display_settings,
+        )
+        .as_bool()
+    };
+    display_settings_found.then_some(DisplaySettings {
+        width: display_settings.dmPelsWidth,
+        height: display_settings.dmPelsHeight,
+        refresh_rate: display_settings.dmDisplayFrequency,
+    })
+}
+
+#[cfg(not(windows))]
+fn current_display_settings(_display_device: Option<&[u8; 32]>) -> Option<DisplaySettings> {
+    None
+}
+
+#[cfg(windows)]
+fn display_device_pcstr(display_device: Option<&[u8; 32]>) -> PCSTR {
+    display_device.map_or(PCSTR::null(), |name| PCSTR(name.as_ptr()))
+}
+
+fn validate_refresh_rate(
+    display_device: Option<&[u8; 32]>,
+    width: u32,
+    height: u32,
+    refresh_rate: u32,
+) -> u32 {
+    let supported_refresh_rates = supported_refresh_rates(display_device, width, height);
+    // Resolutions not matching any display mode (e.g. custom window sizes) cannot be validated
+    if supported_refresh_rates.is_empty() || supported_refresh_rates.contains(&refresh_rate) {
+        return refresh_rate;
+    }
+
+    let fallback_refresh_rate = current_display_settings(display_device)
+        .map_or(60, |display_settings| display_settings.refresh_rate);
+    log::warn!(
+        "refresh_rate = {} is not supported at {}x{} (supported: {:?}), falling back to {}",
+        refresh_rate,
+        width,
+        height,
+        supported_refresh_rates,
+        fallback_refresh_rate
+    );
+    fallback_refresh_rate
+}
+
+#[cfg(windows)]
+fn supported_refresh_rates(display_device: Option<&[u8; 32]>, width: u32, height: u32) -> Vec<u32> {
+    let mut refresh_rates = Vec::new();
+    let mut mode_index = 0;
+    loop {
+        let mut display_mode = DEVMODEA {
+            dmSize: std::mem::size_of::<DEVMODEA>() as u16,
+            ..Default::default()
+        };
+        let display_mode_found = unsafe {
+            EnumDisplaySettingsA(
+                display_device_pcstr(display_device),
+                ENUM_DISPLAY_SETTINGS_MODE(mode_index),
+                &mut display_mode,
+            )
+            .as_bool()
+        };
+        if !display_mode_found {
+            break;
+        }
+        if display_mode.dmPelsWidth == width
+            && display_mode.dmPelsHeight == height
+            && !refresh_rates.contains(&display_mode.dmDisplayFrequency)
+        {
+            refresh_rates.push(display_mode.dmDisplayFrequency);
+        }
+        mode_index += 1;
+    }
+    refresh_rates
+}
+
+#[cfg(not(windows))]
+fn supported_refresh_rates(
+    _display_device: Option<&[u8; 32]>,
+    _width: u32,
+    _height: u32,
+) -> Vec<u32> {
+    Vec::new()
+}
+
+fn merge_game_section(table: &toml::Table, game_type: &GameType) -> toml::Table {
+    let section_name = match game_type {
+        GameType::FF7(_) => "ff7",
+        GameType::FF8 => "ff8",
+    };
+    let mut merged_table = table.clone();
+    if let Some(section) = table.get(section_name).and_then(|value| value.as_table()) {
+        merged_table.extend(section.clone());
+    }
+    merged_table
+}
+
+/// Table of the shipped default config, used in place of a config file that cannot be parsed
+pub fn default_config_table() -> toml::Table {
+    toml::from_str(DEFAULT_CONFIG_FILE).expect("the default config file is valid TOML")
+}
+
+fn write_default_config_file(path: &str) -> Result<()> {
+    std::fs::write(path, DEFAULT_CONFIG_FILE)?;
+    Ok(())
+}
+
+/// Backs up the config file to <path>.bak and writes the default one in its place
+pub fn reset_config_file(path: &str) -> Result<()> {
+    if std::fs::exists(path).is_ok_and(|exists| exists) {
+        let backup_path = format!("{path}.bak");
+        std::fs::copy(path, &backup_path)?;
+        log::info!("Config file {path} backed up to {backup_path}");
+    }
+    write_default_config_file(path)?;
+    log::info!("Config file {path} reset to the default one");
+    Ok(())
+}
+
+/// Writes the default config file with the imported root values in place of the default ones
+pub fn write_imported_config_file(path: &str, settings: &[(&str, toml::Value)]) -> Result<()> {
+    let mut config_file = String::new();
+    for line in DEFAULT_CONFIG_FILE.lines() {
+        let imported_setting = settings
+            .iter()
+            .find(|(key, _)| line.starts_with(&format!("{key} = ")));
+        match imported_setting {
+            Some((key, value)) => config_file += &format!("{key} = {value}"),
+            None => config_file += line,
+        }
+        config_file.push('\n');
+    }
+    std::fs::write(path, config_file)?;
+    Ok(())
+}
+
+/// 0 stands for the auto detected refresh rate, "auto" is its readable form
+fn parse_refresh_rate(value: &toml::Value) -> u32 {
+    match value {
+        toml::Value::Integer(refresh_rate) => (*refresh_rate).max(0) as u32,
+        toml::Value::String(refresh_rate) if refresh_rate.eq_ignore_ascii_case("auto") => 0,
+        _ => {
+            log::warn!("refresh_rate {value} is neither \"auto\" nor a number, using auto");
+            0
+        }
+    }
+}
+
+/// Strings are only read as hex with a 0x prefix, "3" and 3 are the same mask
+fn parse_affinity_mask(value: &toml::Value) -> Option<u64> {
+    let affinity_mask = match value {
+        toml::Value::Integer(affinity_mask) => u64::try_from(*affinity_mask).ok(),
+        toml::Value::String(affinity_mask) => match affinity_mask.strip_prefix("0x") {
+            Some(hex_mask) => u64::from_str_radix(hex_mask, 16).ok(),
+            None => affinity_mask.parse().ok(),
+        },
+        _ => None,
+    };
+    if affinity_mask.is_none() {
+        log::warn!("affinity_mask {value} is not a valid bit mask, ignoring it");
+    }
+    affinity_mask
+}
+
+fn parse_send_order(send_order: &[toml::Value]) -> Vec<HandshakeStep> {
+    send_order
+        .iter()
+        .filter_map(|value| {
+            let step = value.as_str().and_then(|value| value.parse().ok());
+            if step.is_none() {
+                log::warn!("send_order step {value} is unknown, ignoring it");
+            }
+            step
         })
+        .collect()
+}
+
+fn parse_payload_overrides(overrides: &toml::Table) -> Vec<(u32, String)> {
+    overrides
+        .iter()
+        .filter_map(|(key, value)| {
+            let Ok(id) = key.parse::<u32>() else {
+                log::warn!("overrides key {key} is not a message id, ignoring it");
+                return None;
+            };
+            let Some(payload) = value.as_str() else {
+                log::warn!("overrides value of message {id} is not a string, ignoring it");
+                return None;
+            };
+            Some((id, payload.to_string()))
+        })
+        .collect()
+}
+
+fn parse_volume(table: &toml::Table, key: &str) -> i32 {
+    let value = table
+        .get(key)
+        .and_then(|value| value.as_integer())
+        .unwrap_or(MAX_VOLUME as i64);
+    let clamped_value = value.clamp(0, MAX_VOLUME as i64);
+    if clamped_value != value {
+        log::warn!("{key} = {value} is out of range (0-{MAX_VOLUME}), clamped to {clamped_value}");
+    }
+    clamped_value as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from_toml(name: &str, contents: &str, game_type: &GameType) -> Config {
+        let path = std::env::temp_dir().join(format!("ff78launcher_test_{name}.toml"));
+        std::fs::write(&path, contents).unwrap();
+        let table = Config::read_config_table(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        Config::from_config_table(&table, game_type).unwrap()
+    }
+
+    #[test]
+    fn ff7_forces_chocobo_off_and_keeps_pause_for_ffnx() {
+        let config = config_from_toml(
+            "ff7_forced_flags",
+            "pause_game_on_background = true\nlaunch_chocobo = true\n",
+            &GameType::FF7(StoreType::Standard),
+        );
+        assert!(config.pause_game_on_background);
+        assert!(!config.launch_chocobo);
+    }
+
+    #[test]
+    fn ff8_keeps_pause_and_chocobo() {
+        let config = config_from_toml(
+            "ff8_flags",
+            "pause_game_on_background = true\nlaunch_chocobo = true\n",
+            &GameType::FF8,
+        );
+        assert!(config.pause_game_on_background);
+        assert!(config.launch_chocobo);
+    }
+
+    #[test]
+    fn window_mode_without_resolution_falls_back_to_640x480() {
+        let config = config_from_toml(
+            "window_fallback",
+            "fullscreen = false\n",
+            &GameType::FF7(StoreType::Standard),
+        );
+        assert_eq!(config.window_width, 640);
+        assert_eq!(config.window_height, 480);
+        assert_eq!(config.refresh_rate, 60);
+    }
+
+    #[test]
+    fn fullscreen_without_desktop_resolution_falls_back_to_640x480() {
+        let config = config_from_toml(
+            "fullscreen_fallback",
+            "fullscreen = true\nfullscreen_use_desktop_resolution = false\n",
+            &GameType::FF7(StoreType::Standard),
+        );
+        assert!(config.fullscreen);
+        assert_eq!(config.window_width, 640);
+        assert_eq!(config.window_height, 480);
+    }
+
+    #[test]
+    fn borderless_display_mode_overrides_fullscreen() {
+        let config = config_from_toml(
+            "borderless_mode",
+            "fullscreen = true\ndisplay_mode = \"borderless\"\nwindow_width = 1920\nwindow_height = 1080\n",
+            &GameType::FF8,
+        );
+        assert_eq!(config.display_mode, DisplayMode::Borderless);
+        assert!(!config.fullscreen);
+        assert_eq!(config.window_width, 1920);
+    }
+
+    #[test]
+    fn auto_refresh_rate_matches_zero() {
+        let auto_config = config_from_toml(
+            "refresh_rate_auto",
+            "fullscreen = false\nrefresh_rate = \"auto\"\n",
+            &GameType::FF8,
+        );
+        let zero_config = config_from_toml(
+            "refresh_rate_zero",
+            "fullscreen = false\nrefresh_rate = 0\n",
+            &GameType::FF8,
+        );
+        assert_eq!(auto_config.refresh_rate, zero_config.refresh_rate);
+        assert_eq!(auto_config.refresh_rate, 60);
+    }
+
+    #[test]
+    fn explicit_values_are_parsed() {
+        let config = config_from_toml(
+            "explicit_values",
+            "window_width = 1280\nwindow_height = 720\nkeep_aspect_ratio = true\nsfx_volume = 40\nmusic_volume = 60\n",
+            &GameType::FF8,
+        );
+        assert_eq!(config.window_width, 1280);
+        assert_eq!(config.window_height, 720);
+        assert!(config.keep_aspect_ratio);
+        assert_eq!(config.sfx_volume, 40);
+        assert_eq!(config.music_volume, 60);
+    }
+
+    #[test]
+    fn out_of_range_volumes_are_clamped() {
+        let config = config_from_toml(
+            "volume_clamp",
+            "sfx_volume = 5000\nmusic_volume = -20\n",
+            &GameType::FF8,
+        );
+        assert_eq!(config.sfx_volume, 100);
+        assert_eq!(config.music_volume, 0);
+    }
+
+    #[test]
+    fn missing_volumes_default_to_the_max_volume() {
+        let config = Config::from_config_table(&toml::Table::new(), &GameType::FF8).unwrap();
+        assert_eq!(config.sfx_volume, 100);
+        assert_eq!(config.music_volume, 100);
+    }
+
+    #[test]
+    fn negative_affinity_masks_are_rejected() {
+        assert_eq!(parse_affinity_mask(&toml::Value::Integer(-1)), None);
+        assert_eq!(parse_affinity_mask(&toml::Value::Integer(3)), Some(3));
+    }
+
+    #[test]
+    fn affinity_mask_strings_are_hex_only_with_0x() {
+        let parse = |value: &str| parse_affinity_mask(&toml::Value::String(value.to_string()));
+        assert_eq!(parse("0x10"), Some(16));
+        assert_eq!(parse("10"), Some(10));
+        assert_eq!(parse("ff"), None);
+        assert_eq!(parse("-1"), None);
+    }
+
+    #[test]
+    fn imported_settings_replace_the_default_values() {
+        let path = std::env::temp_dir().join("ff78launcher_test_imported.toml");
+        let path = path.to_str().unwrap();
+        write_imported_config_file(
+            path,
+            &[
+                ("window_width", toml::Value::Integer(1280)),
+                ("window_height", toml::Value::Integer(720)),
+                ("fullscreen", toml::Value::Boolean(true)),
+                ("music_volume", toml::Value::Integer(40)),
+            ],
+        )
+        .unwrap();
+        let table = Config::read_config_table(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(table["window_width"].as_integer(), Some(1280));
+        assert_eq!(table["window_height"].as_integer(), Some(720));
+        assert_eq!(table["fullscreen"].as_bool(), Some(true));
+        assert_eq!(table["music_volume"].as_integer(), Some(40));
+        assert_eq!(table["sfx_volume"].as_integer(), Some(100));
+    }
+
+    #[test]
+    fn default_config_table_matches_the_default_config_file() {
+        let path = std::env::temp_dir().join("ff78launcher_test_default_table.toml");
+        let path = path.to_str().unwrap();
+        write_default_config_file(path).unwrap();
+        let file_config =
+            Config::from_config_table(&Config::read_config_table(path).unwrap(), &GameType::FF8)
+                .unwrap();
+        std::fs::remove_file(path).unwrap();
+        let fallback_config =
+            Config::from_config_table(&default_config_table(), &GameType::FF8).unwrap();
+        assert_eq!(fallback_config.sfx_volume, 100);
+        assert_eq!(fallback_config.music_volume, 100);
+        assert_eq!(format!("{fallback_config:?}"), format!("{file_config:?}"));
+    }
+
+    #[test]
+    fn reset_config_keeps_a_backup() {
+        let path = std::env::temp_dir().join("ff78launcher_test_reset.toml");
+        let path = path.to_str().unwrap();
+        let backup_path = format!("{path}.bak");
+        std::fs::write(path, "window_width = [").unwrap();
+        reset_config_file(path).unwrap();
+        let contents = std::fs::read_to_string(path).unwrap();
+        let backup_contents = std::fs::read_to_string(&backup_path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(&backup_path).unwrap();
+        assert_eq!(contents, DEFAULT_CONFIG_FILE);
+        assert_eq!(backup_contents, "window_width = [");
+    }
+
+    #[test]
+    fn payload_overrides_are_keyed_by_message_id() {
+        let config = config_from_toml(
+            "payload_overrides",
+            "[overrides]\n17 = \"custom\"\nversion = \"ignored\"\n18 = 1\n",
+            &GameType::FF8,
+        );
+        assert_eq!(config.payload_overrides, vec![(17, "custom".to_string())]);
+    }
+
+    #[test]
+    fn send_order_must_end_with_launcher_completed() {
+        let config = config_from_toml(
+            "send_order",
+            "send_order = [\"install_dir\", \"unknown\", \"launcher_completed\"]\n",
+            &GameType::FF8,
+        );
+        assert_eq!(
+            config.send_order,
+            vec![HandshakeStep::InstallDir, HandshakeStep::LauncherCompleted]
+        );
+
+        let config = Config {
+            send_order: vec![HandshakeStep::LauncherCompleted, HandshakeStep::InstallDir],
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn game_section_overrides_root_values() {
+        let contents = "music_volume = 50\n[ff7]\nmusic_volume = 70\n[ff8]\nsfx_volume = 30\n";
+        let ff7_config =
+            config_from_toml("ff7_section", contents, &GameType::FF7(StoreType::Standard));
+        assert_eq!(ff7_config.music_volume, 70);
+        let ff8_config = config_from_toml("ff8_section", contents, &GameType::FF8);
+        assert_eq!(ff8_config.music_volume, 50);
+        assert_eq!(ff8_config.sfx_volume, 30);
     }
 }
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+/// Launch failures the user can act on, main shows a tailored message box for each of them
+#[derive(Debug)]
+pub enum LauncherError {
+    NoProcessFound,
+    MultipleProcessesFound(Vec<String>),
+    ConfigNotFound(String),
+    ConfigParseFailed {
+        config_path: String,
+        error: String,
+    },
+    GameExecutableNotFound(String),
+    ArchitectureMismatch {
+        process_path: PathBuf,
+        game_machine: String,
+        launcher_machine: String,
+    },
+    #[cfg(windows)]
+    SharedMemoryMappingFailed {
+        name: String,
+        error: windows::core::Error,
+    },
+    HandshakeTimeout {
+        timeout_secs: u64,
+    },
+}
+
+impl std::fmt::Display for LauncherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LauncherError::NoProcessFound => write!(f, "No process to start found!"),
+            LauncherError::MultipleProcessesFound(processes) => {
+                write!(f, "More than one process to start found: {:?}", processes)
+            }
+            LauncherError::ConfigNotFound(config_path) => {
+                write!(f, "Config file not found: {config_path}")
+            }
+            LauncherError::ConfigParseFailed { config_path, error } => {
+                write!(f, "Config file {config_path} could not be parsed: {error}")
+            }
+            LauncherError::GameExecutableNotFound(game_executable) => {
+                write!(f, "Game executable not found: {game_executable}")
+            }
+            LauncherError::ArchitectureMismatch {
+                process_path,
+                game_machine,
+                launcher_machine,
+            } => write!(
+                f,
+                "{:?} is a {game_machine} executable but the launcher is {launcher_machine}, use the {game_machine} launcher build",
+                process_path
+            ),
+            #[cfg(windows)]
+            LauncherError::SharedMemoryMappingFailed { name, error } => {
+                write!(f, "Mapping the shared memory {name} failed: {:?}", error)
+            }
+            LauncherError::HandshakeTimeout { timeout_secs } => write!(
+                f,
+                "Timed out after {timeout_secs} seconds waiting for the game to read the launcher message"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LauncherError {}
@@ -1,15 +1,41 @@
-use std::{io::Write, os::windows::ffi::OsStrExt};
+use std::{
+    ffi::c_void,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+#[cfg(windows)]
 use windows::Win32::{
+    Foundation::{WAIT_OBJECT_0, WAIT_TIMEOUT},
     System::{
         Com::CoTaskMemFree,
-        Threading::{ReleaseSemaphore, WaitForSingleObject, INFINITE},
+        Registry::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+        Threading::{ReleaseSemaphore, WaitForSingleObject},
     },
     UI::Shell::{FOLDERID_Documents, SHGetKnownFolderPath, KF_FLAG_DEFAULT},
 };
 
-use crate::{Context, GameType, LauncherContext, StoreType, APP_NAME};
+use crate::{
+    channel::GameChannel, error::LauncherError, Context, GameType, StoreType, APP_NAME,
+    APP_VERSION, LAUNCHER_MEMORY_OFFSET, SHARED_MEMORY_SIZE,
+};
+#[cfg(windows)]
+use crate::{
+    process::steam_app_id,
+    registry::{read_registry_dword, read_registry_string},
+    LauncherContext,
+};
+
+const SUPPORTED_GAME_LANGS: [&str; 6] = ["de", "en", "es", "fr", "it", "ja"];
+const FALLBACK_GAME_LANG: &str = "en";
+const SAVE_BACKUPS_DIR: &str = "save_backups";
+#[cfg(windows)]
+const GAME_WAIT_SLICE: Duration = Duration::from_millis(250);
 
 const FF7_USER_SAVE_DIR: u32 = 10;
 const FF7_DOC_DIR: u32 = 11;
@@ -35,259 +61,341 @@ const ESTORE_LOCALE_DATA_DIR: u32 = 12;
 const ESTORE_GAME_VERSION: u32 = 17;
 const ESTORE_END_USER_INFO: u32 = 20;
 
-pub fn send_locale_data_dir(ctx: &Context, launcher_ctx: &mut LauncherContext) {
-    let payload: Vec<u16> = (String::from("lang-") + &ctx.game_lang)
-        .encode_utf16()
-        .collect();
-    let mut bytes = Vec::<u8>::new();
-    bytes.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_LOCALE_DATA_DIR,
-            GameType::FF7(StoreType::EStore) => ESTORE_LOCALE_DATA_DIR,
-            GameType::FF8 => FF8_LOCALE_DATA_DIR,
+// The game appends its data file paths to the install dir, Windows paths are limited to 260
+// characters (MAX_PATH) unless long paths are enabled
+const LONG_INSTALL_DIR_LEN: usize = 200;
+
+pub fn send_locale_data_dir(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &locale_data_dir_message(ctx))
+}
+
+fn game_locale(ctx: &Context) -> &str {
+    if let Some(locale_override) = &ctx.config.locale_override {
+        if SUPPORTED_GAME_LANGS.contains(&locale_override.as_str()) {
+            log::info!(
+                "Using language {locale_override} from the config instead of {} from the executable name",
+                ctx.game_lang
+            );
+            return locale_override;
         }
-        .to_le_bytes(),
-    );
-    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    bytes.append(&mut payload.iter().flat_map(|b| b.to_le_bytes()).collect());
-    unsafe {
-        std::ptr::copy(
-            bytes.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            bytes.len(),
+        log::warn!(
+            "Unsupported language {locale_override}, expected one of {:?}",
+            SUPPORTED_GAME_LANGS
         );
-    };
-    log::info!(
-        "send_locale_data_dir -> {}, {}, {}",
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-        String::from_utf16_lossy(&payload)
+    }
+    if SUPPORTED_GAME_LANGS.contains(&ctx.game_lang.as_str()) {
+        return &ctx.game_lang;
+    }
+    log::warn!(
+        "Unsupported game language {}, using {FALLBACK_GAME_LANG} locale instead",
+        ctx.game_lang
     );
+    FALLBACK_GAME_LANG
+}
 
-    wait_for_game(launcher_ctx);
+pub fn locale_data_dir_message(ctx: &Context) -> Vec<u8> {
+    let payload: Vec<u16> = (String::from("lang-") + game_locale(ctx))
+        .encode_utf16()
+        .collect();
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_LOCALE_DATA_DIR,
+        GameType::FF7(StoreType::EStore) => ESTORE_LOCALE_DATA_DIR,
+        GameType::FF8 => FF8_LOCALE_DATA_DIR,
+    };
+    payload_message(ctx, "send_locale_data_dir", id, payload)
 }
 
-pub fn send_user_save_dir(ctx: &Context, launcher_ctx: &mut LauncherContext) -> Result<()> {
-    let mut payload = get_game_metadata_path(ctx)?;
-    if std::fs::exists("save").is_ok_and(|v| v) {
-        payload += "\\save";
-    } else {
-        let paths = std::fs::read_dir(&payload)?;
-        let user_path = paths
-            .filter_map(|p| p.ok().map(|p| p.path()))
-            .filter(|p| {
-                p.is_dir()
-                    && p.file_name()
-                        .expect("Always have filename")
-                        .to_string_lossy()
-                        .starts_with("user_")
-            })
-            .last();
-        if let Some(user_path) = user_path {
-            payload += "\\";
-            payload += user_path
-                .file_name()
-                .expect("Always have filename")
-                .to_string_lossy()
-                .as_ref()
-        }
-    }
-    let payload: Vec<u16> = payload.encode_utf16().collect();
+pub fn send_user_save_dir(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &user_save_dir_message(ctx)?)
+}
 
-    let mut bytes = Vec::<u8>::new();
-    bytes.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_USER_SAVE_DIR,
-            GameType::FF7(StoreType::EStore) => ESTORE_USER_SAVE_DIR,
-            GameType::FF8 => FF8_USER_SAVE_DIR,
-        }
-        .to_le_bytes(),
-    );
-    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    bytes.append(&mut payload.iter().flat_map(|b| b.to_le_bytes()).collect());
-    unsafe {
-        std::ptr::copy(
-            bytes.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            bytes.len(),
-        );
+pub fn user_save_dir_message(ctx: &Context) -> Result<Vec<u8>> {
+    let payload: Vec<u16> = get_user_save_dir(ctx)?.encode_utf16().collect();
+
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_USER_SAVE_DIR,
+        GameType::FF7(StoreType::EStore) => ESTORE_USER_SAVE_DIR,
+        GameType::FF8 => FF8_USER_SAVE_DIR,
     };
-    log::info!(
-        "send_user_save_dir -> {}, {}, {}",
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-        String::from_utf16_lossy(&payload)
-    );
+    let bytes = payload_message(ctx, "send_user_save_dir", id, payload);
 
-    wait_for_game(launcher_ctx);
-    Ok(())
+    Ok(bytes)
 }
 
-pub fn send_user_doc_dir(ctx: &Context, launcher_ctx: &mut LauncherContext) -> Result<()> {
+pub fn send_user_doc_dir(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &user_doc_dir_message(ctx)?)
+}
+
+pub fn user_doc_dir_message(ctx: &Context) -> Result<Vec<u8>> {
     let payload: Vec<u16> = get_game_metadata_path(ctx)?.encode_utf16().collect();
-    let mut bytes = Vec::<u8>::new();
-    bytes.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_DOC_DIR,
-            GameType::FF7(StoreType::EStore) => ESTORE_DOC_DIR,
-            GameType::FF8 => FF8_DOC_DIR,
-        }
-        .to_le_bytes(),
-    );
-    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    bytes.append(&mut payload.iter().flat_map(|b| b.to_le_bytes()).collect());
-    bytes.push(0);
-    unsafe {
-        std::ptr::copy(
-            bytes.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            bytes.len(),
-        );
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_DOC_DIR,
+        GameType::FF7(StoreType::EStore) => ESTORE_DOC_DIR,
+        GameType::FF8 => FF8_DOC_DIR,
     };
-    log::info!(
-        "send_user_doc_dir -> {}, {}, {}",
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-        String::from_utf16_lossy(&payload)
-    );
+    let mut bytes = payload_message(ctx, "send_user_doc_dir", id, payload);
+    // This message always ended with a 0 byte not counted in len, the game may rely on it
+    bytes.push(0);
 
-    wait_for_game(launcher_ctx);
-    Ok(())
+    Ok(bytes)
 }
 
-pub fn send_install_dir(ctx: &Context, launcher_ctx: &mut LauncherContext) -> Result<()> {
-    let cwd = std::path::absolute(".")?;
-    let payload: Vec<u16> = cwd.into_os_string().encode_wide().collect();
-    let mut bytes = Vec::<u8>::new();
-    bytes.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_INSTALL_DIR,
-            GameType::FF7(StoreType::EStore) => ESTORE_INSTALL_DIR,
-            GameType::FF8 => FF8_INSTALL_DIR,
-        }
-        .to_le_bytes(),
-    );
-    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    bytes.append(&mut payload.iter().flat_map(|b| b.to_le_bytes()).collect());
-    unsafe {
-        std::ptr::copy(
-            bytes.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            bytes.len(),
+pub fn send_install_dir(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &install_dir_message(ctx)?)
+}
+
+pub fn install_dir_message(ctx: &Context) -> Result<Vec<u8>> {
+    let install_dir = get_install_dir(ctx)?;
+    #[cfg(windows)]
+    let payload: Vec<u16> = install_dir.as_os_str().encode_wide().collect();
+    #[cfg(not(windows))]
+    let payload: Vec<u16> = install_dir.to_string_lossy().encode_utf16().collect();
+    if is_long_install_dir(payload.len()) {
+        log::warn!(
+            "The install dir {:?} is {} characters long, the game may fail to open its data files past 260 characters",
+            install_dir,
+            payload.len()
         );
+    }
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_INSTALL_DIR,
+        GameType::FF7(StoreType::EStore) => ESTORE_INSTALL_DIR,
+        GameType::FF8 => FF8_INSTALL_DIR,
     };
-    log::info!(
-        "send_install_dir -> {:?}, {:?}, {}",
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-        String::from_utf16_lossy(&payload)
-    );
+    let bytes = payload_message(ctx, "send_install_dir", id, payload);
 
-    wait_for_game(launcher_ctx);
-    Ok(())
+    Ok(bytes)
 }
 
-pub fn send_game_version(ctx: &Context, launcher_ctx: &mut LauncherContext) {
-    let payload: Vec<u16> = (APP_NAME.to_string() + " 1.0.0").encode_utf16().collect();
-    let mut bytes = Vec::<u8>::new();
-    bytes.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_GAME_VERSION,
-            GameType::FF7(StoreType::EStore) => ESTORE_GAME_VERSION,
-            GameType::FF8 => FF8_GAME_VERSION,
-        }
-        .to_le_bytes(),
-    );
-    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    bytes.append(&mut payload.iter().flat_map(|b| b.to_le_bytes()).collect());
-    unsafe {
-        std::ptr::copy(
-            bytes.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            bytes.len(),
-        );
+/// Whether the data file paths of the game may not fit in MAX_PATH, the launch goes on anyway as
+/// the actual limit of the game is not known
+fn is_long_install_dir(len: usize) -> bool {
+    len > LONG_INSTALL_DIR_LEN
+}
+
+pub fn send_game_version(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &game_version_message(ctx))
+}
+
+pub fn game_version_message(ctx: &Context) -> Vec<u8> {
+    let payload: Vec<u16> = (APP_NAME.to_string() + " " + APP_VERSION)
+        .encode_utf16()
+        .collect();
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_GAME_VERSION,
+        GameType::FF7(StoreType::EStore) => ESTORE_GAME_VERSION,
+        GameType::FF8 => FF8_GAME_VERSION,
     };
-    log::info!(
-        "send_game_version -> {:?}, {:?}, {}",
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
-        String::from_utf16_lossy(&payload)
-    );
+    payload_message(ctx, "send_game_version", id, payload)
+}
 
-    wait_for_game(launcher_ctx);
+pub fn send_disable_cloud(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    match disable_cloud_message(ctx) {
+        Some(bytes) => send_message(ctx, channel, &bytes),
+        None => Ok(()),
+    }
 }
 
-pub fn send_disable_cloud(ctx: &Context, launcher_ctx: &mut LauncherContext) {
+pub fn disable_cloud_message(ctx: &Context) -> Option<Vec<u8>> {
     if let GameType::FF7(StoreType::EStore) = ctx.game_to_launch {
-        return;
+        return None;
     }
 
-    let mut launcher_game_part = Vec::<u8>::new();
-    launcher_game_part.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(_) => FF7_DISABLE_CLOUD,
-            GameType::FF8 => FF8_DISABLE_CLOUD,
-        }
-        .to_le_bytes(),
-    );
-    unsafe {
-        std::ptr::copy(
-            launcher_game_part.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            launcher_game_part.len(),
-        );
+    let id = match ctx.game_to_launch {
+        GameType::FF7(_) => FF7_DISABLE_CLOUD,
+        GameType::FF8 => FF8_DISABLE_CLOUD,
     };
-    log::info!("send_disable_cloud -> {launcher_game_part:?}");
+    Some(id_message("send_disable_cloud", id, None))
+}
 
-    wait_for_game(launcher_ctx);
+pub fn send_bg_pause_enabled(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    match bg_pause_enabled_message(ctx) {
+        Some(bytes) => send_message(ctx, channel, &bytes),
+        None => Ok(()),
+    }
 }
 
-pub fn send_bg_pause_enabled(ctx: &Context, launcher_ctx: &mut LauncherContext) {
+pub fn bg_pause_enabled_message(ctx: &Context) -> Option<Vec<u8>> {
     if let GameType::FF7(_) = ctx.game_to_launch {
-        return;
+        return None;
     }
 
-    let mut launcher_game_part = Vec::<u8>::new();
-    launcher_game_part.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(_) => unreachable!(),
-            GameType::FF8 => FF8_BG_PAUSE_ENABLED,
+    Some(id_message(
+        "send_bg_pause_enabled",
+        FF8_BG_PAUSE_ENABLED,
+        Some(1),
+    ))
+}
+
+/// The configured Steam user id, or the one logged in the Steam client
+fn steam_user_id(ctx: &Context) -> Option<u64> {
+    ctx.config.steam_user_id.or_else(steam_active_user)
+}
+
+/// The user logged in the Steam client
+#[cfg(windows)]
+fn steam_active_user() -> Option<u64> {
+    match read_registry_dword(
+        HKEY_CURRENT_USER,
+        "Software\\Valve\\Steam\\ActiveProcess",
+        "ActiveUser",
+    ) {
+        // ActiveUser is 0 while no user is logged in
+        Ok(0) => None,
+        Ok(active_user) => Some(active_user as u64),
+        Err(err) => {
+            log::debug!("Steam ActiveUser not readable: {err}");
+            None
         }
-        .to_le_bytes(),
-    );
-    launcher_game_part.extend_from_slice(&1u32.to_le_bytes());
-    unsafe {
-        std::ptr::copy(
-            launcher_game_part.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            launcher_game_part.len(),
-        );
-    };
-    log::info!("send_bg_pause_enabled -> {launcher_game_part:?}");
+    }
+}
+
+#[cfg(not(windows))]
+fn steam_active_user() -> Option<u64> {
+    None
+}
+
+pub fn send_launcher_completed(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    send_message(ctx, channel, &launcher_completed_message(ctx))
+}
 
-    wait_for_game(launcher_ctx);
+pub fn launcher_completed_message(ctx: &Context) -> Vec<u8> {
+    let id = match ctx.game_to_launch {
+        GameType::FF7(StoreType::Standard) => FF7_END_USER_INFO,
+        GameType::FF7(StoreType::EStore) => ESTORE_END_USER_INFO,
+        GameType::FF8 => FF8_END_USER_INFO,
+    };
+    id_message("send_launcher_completed", id, None)
 }
 
-pub fn send_launcher_completed(ctx: &Context, launcher_ctx: &mut LauncherContext) {
-    let mut launcher_game_part = Vec::<u8>::new();
-    launcher_game_part.extend_from_slice(
-        &match ctx.game_to_launch {
-            GameType::FF7(StoreType::Standard) => FF7_END_USER_INFO,
-            GameType::FF7(StoreType::EStore) => ESTORE_END_USER_INFO,
-            GameType::FF8 => FF8_END_USER_INFO,
+/// Builds and logs a string message, its payload is replaced by the `[overrides]` one of its id
+fn payload_message(ctx: &Context, name: &str, id: u32, payload: Vec<u16>) -> Vec<u8> {
+    let payload_override = ctx
+        .config
+        .payload_overrides
+        .iter()
+        .find(|(override_id, _)| *override_id == id);
+    let payload = match payload_override {
+        Some((_, payload_override)) => {
+            log::warn!("{name} payload overridden by [overrides] {id}");
+            payload_override.encode_utf16().collect()
         }
-        .to_le_bytes(),
+        None => payload,
+    };
+    let bytes = string_message(id, &payload);
+    log_sent_message(name, &bytes, &payload);
+    bytes
+}
+
+/// String messages are the id and the payload length as little endian u32, followed by the UTF-16
+/// payload. The length counts UTF-16 code units (not bytes) and no null terminator follows the
+/// payload.
+fn string_message(id: u32, payload: &[u16]) -> Vec<u8> {
+    let mut bytes = Vec::<u8>::new();
+    bytes.extend_from_slice(&id.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend(payload.iter().flat_map(|c| c.to_le_bytes()));
+    bytes
+}
+
+/// Messages without a string payload are the id as little endian u32, optionally followed by a
+/// little endian u32 value
+fn id_message(name: &str, id: u32, value: Option<u32>) -> Vec<u8> {
+    let mut bytes = Vec::<u8>::new();
+    bytes.extend_from_slice(&id.to_le_bytes());
+    if let Some(value) = value {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    log::info!("{name} -> {bytes:?}");
+    bytes
+}
+
+fn log_sent_message(name: &str, bytes: &[u8], payload: &[u16]) {
+    let id = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let payload = String::from_utf16_lossy(payload);
+    log::info!(
+        id = id, len = len, payload = payload.as_str();
+        "{name} -> {id}, {len}, {payload}"
     );
-    unsafe {
-        std::ptr::copy(
-            launcher_game_part.as_ptr(),
-            launcher_ctx.launcher_memory_part as _,
-            launcher_game_part.len(),
-        );
+}
+
+/// One line listing the id of every sent message in order, with its payload or value if any
+pub fn handshake_summary(messages: &[Vec<u8>]) -> String {
+    messages
+        .iter()
+        .filter(|bytes| bytes.len() >= 4)
+        .map(|bytes| {
+            let id = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            match bytes.len() {
+                4 => id.to_string(),
+                8 => {
+                    let value = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+                    format!("{id}={value}")
+                }
+                _ => {
+                    let payload = bytes[8..]
+                        .chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect::<Vec<u16>>();
+                    format!("{id}={:?}", String::from_utf16_lossy(&payload))
+                }
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Builds the response written back to the game from the payload of its request
+type GameMessageHandler = fn(&[u16]) -> Vec<u8>;
+
+/// Handlers of the game requests by message id
+const GAME_MESSAGE_HANDLERS: &[(u32, GameMessageHandler)] = &[];
+
+pub fn read_game_message(game_memory_part: *const c_void) -> (u32, Vec<u16>) {
+    let (id, len) = unsafe {
+        (
+            std::ptr::read_unaligned(game_memory_part as *const u32),
+            std::ptr::read_unaligned((game_memory_part as *const u32).offset(1)),
+        )
     };
-    log::info!("send_launcher_completed -> {launcher_game_part:?}");
+    let payload_len = (len as usize).min((LAUNCHER_MEMORY_OFFSET - 8) / 2);
+    let payload = unsafe {
+        std::slice::from_raw_parts(
+            (game_memory_part as *const u8).offset(8) as *const u16,
+            payload_len,
+        )
+    }
+    .to_vec();
+    let payload_text = String::from_utf16_lossy(&payload);
+    log::info!(
+        id = id, len = len, payload = payload_text.as_str();
+        "receive_game_message <- {id}, {len}, {payload_text}"
+    );
 
-    wait_for_game(launcher_ctx);
+    (id, payload)
+}
+
+/// Sends the response to a game request, the unknown ones are acknowledged with their id and an
+/// empty payload so the protocol can be extended one request at a time
+pub fn answer_game_message(
+    channel: &mut dyn GameChannel,
+    id: u32,
+    payload: &[u16],
+    timeout: Duration,
+) -> Result<()> {
+    let response = match GAME_MESSAGE_HANDLERS
+        .iter()
+        .find(|(handler_id, _)| *handler_id == id)
+    {
+        Some((_, handler)) => handler(payload),
+        None => {
+            log::info!("Unknown game message {id}, acknowledging it");
+            string_message(id, &[])
+        }
+    };
+    log_sent_message("send_game_message_response", &response, &[]);
+    channel.send(&response, timeout)
 }
 
 pub fn write_ffvideo(ctx: &Context) -> Result<()> {
@@ -296,31 +404,34 @@ pub fn write_ffvideo(ctx: &Context) -> Result<()> {
         GameType::FF8 => "ff8video.cfg",
     };
     let filepath = get_game_metadata_path(ctx)? + "\\" + filename;
-    let mut file = std::fs::File::create(filepath)?;
+    let mut bytes = Vec::<u8>::new();
     match ctx.game_to_launch {
         GameType::FF7(_) => {
-            file.write_all(&ctx.config.window_width.to_be_bytes())?;
-            file.write_all(&ctx.config.window_height.to_be_bytes())?;
-            file.write_all(&ctx.config.refresh_rate.to_be_bytes())?;
-            file.write_all(&u32::from(ctx.config.fullscreen).to_be_bytes())?;
-            file.write_all(&0u32.to_be_bytes())?;
-            file.write_all(&u32::from(ctx.config.keep_aspect_ratio).to_be_bytes())?;
-            file.write_all(&u32::from(ctx.config.enable_linear_filtering).to_be_bytes())?;
-            file.write_all(&u32::from(ctx.config.original_mode).to_be_bytes())?;
+            bytes.write_all(&ctx.config.window_width.to_be_bytes())?;
+            bytes.write_all(&ctx.config.window_height.to_be_bytes())?;
+            bytes.write_all(&ctx.config.refresh_rate.to_be_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.fullscreen).to_be_bytes())?;
+            bytes.write_all(&0u32.to_be_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.keep_aspect_ratio).to_be_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.enable_linear_filtering).to_be_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.original_mode).to_be_bytes())?;
+            if ctx.use_ffnx {
+                bytes.write_all(&u32::from(ctx.config.pause_game_on_background).to_be_bytes())?;
+            }
         }
         GameType::FF8 => {
-            file.write_all(&ctx.config.window_width.to_le_bytes())?;
-            file.write_all(&ctx.config.window_height.to_le_bytes())?;
-            file.write_all(&ctx.config.refresh_rate.to_le_bytes())?;
-            file.write_all(&u32::from(ctx.config.fullscreen).to_le_bytes())?;
-            file.write_all(&0u32.to_le_bytes())?;
-            file.write_all(&u32::from(ctx.config.keep_aspect_ratio).to_le_bytes())?;
-            file.write_all(&u32::from(ctx.config.enable_linear_filtering).to_le_bytes())?;
-            file.write_all(&u32::from(ctx.config.original_mode).to_le_bytes())?;
-            file.write_all(&u32::from(ctx.config.pause_game_on_background).to_le_bytes())?;
+            bytes.write_all(&ctx.config.window_width.to_le_bytes())?;
+            bytes.write_all(&ctx.config.window_height.to_le_bytes())?;
+            bytes.write_all(&ctx.config.refresh_rate.to_le_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.fullscreen).to_le_bytes())?;
+            bytes.write_all(&0u32.to_le_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.keep_aspect_ratio).to_le_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.enable_linear_filtering).to_le_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.original_mode).to_le_bytes())?;
+            bytes.write_all(&u32::from(ctx.config.pause_game_on_background).to_le_bytes())?;
         }
     }
-    Ok(())
+    write_file_atomically(&filepath, &bytes)
 }
 
 pub fn write_ffsound(ctx: &Context) -> Result<()> {
@@ -329,43 +440,565 @@ pub fn write_ffsound(ctx: &Context) -> Result<()> {
         GameType::FF8 => "ff8sound.cfg",
     };
     let filepath = get_game_metadata_path(ctx)? + "\\" + filename;
-    let mut file = std::fs::File::create(filepath)?;
-    file.write_all(&ctx.config.sfx_volume.to_le_bytes())?;
-    file.write_all(&ctx.config.music_volume.to_le_bytes())?;
+    let mut bytes = Vec::<u8>::new();
+    bytes.write_all(&ctx.config.sfx_volume.to_le_bytes())?;
+    bytes.write_all(&ctx.config.music_volume.to_le_bytes())?;
+    write_file_atomically(&filepath, &bytes)
+}
+
+/// Copies the input_cfg file over the one the game reads its controls from
+pub fn write_input_cfg(ctx: &Context) -> Result<()> {
+    let Some(input_cfg) = &ctx.config.input_cfg else {
+        return Ok(());
+    };
+    let filename = match ctx.game_to_launch {
+        GameType::FF7(_) => "ff7input.cfg",
+        GameType::FF8 => "ff8input.cfg",
+    };
+    let filepath = get_game_metadata_path(ctx)? + "\\" + filename;
+    let bytes = std::fs::read(input_cfg)
+        .map_err(|err| anyhow::anyhow!("Input cfg {} could not be read: {}", input_cfg, err))?;
+    write_file_atomically(&filepath, &bytes)?;
+    log::info!("Input cfg {input_cfg} copied to {filepath}");
     Ok(())
 }
 
-fn get_game_metadata_path(ctx: &Context) -> Result<String> {
-    let mut game_install_path = String::new();
-    if !matches!(ctx.game_to_launch, GameType::FF7(StoreType::EStore))
-        && !std::fs::exists("data/music_2").is_ok_and(|b| b)
-    {
-        let doc_path = unsafe {
-            let doc_path_pw = SHGetKnownFolderPath(&FOLDERID_Documents, KF_FLAG_DEFAULT, None)?;
-            let doc_path = doc_path_pw.to_string()?;
-            CoTaskMemFree(Some(doc_path_pw.as_ptr() as _));
-            doc_path
-        };
-        game_install_path += &doc_path;
-        game_install_path += "\\Square Enix\\FINAL FANTASY ";
-        game_install_path += match ctx.game_to_launch {
-            GameType::FF7(_) => "VII Steam",
-            GameType::FF8 => "VIII Steam",
+/// Settings left by the original launcher in the video and sound cfg files, read with the layout
+/// write_ffvideo and write_ffsound use
+pub fn read_previous_settings(ctx: &Context) -> Vec<(&'static str, toml::Value)> {
+    let mut settings = Vec::new();
+    let metadata_path = match get_game_metadata_path(ctx) {
+        Ok(metadata_path) => metadata_path,
+        Err(err) => {
+            log::warn!("Metadata directory not found, no settings to import: {err}");
+            return settings;
+        }
+    };
+    let (video_filename, sound_filename) = match ctx.game_to_launch {
+        GameType::FF7(_) => ("ff7video.cfg", "ff7sound.cfg"),
+        GameType::FF8 => ("ff8video.cfg", "ff8sound.cfg"),
+    };
+    let big_endian = matches!(ctx.game_to_launch, GameType::FF7(_));
+
+    match read_cfg_values(&(metadata_path.clone() + "\\" + video_filename), big_endian) {
+        Some(values) if values.len() >= 8 => {
+            settings.push(("window_width", toml::Value::Integer(values[0] as i64)));
+            settings.push(("window_height", toml::Value::Integer(values[1] as i64)));
+            settings.push(("refresh_rate", toml::Value::Integer(values[2] as i64)));
+            settings.push(("fullscreen", toml::Value::Boolean(values[3] != 0)));
+            settings.push(("keep_aspect_ratio", toml::Value::Boolean(values[5] != 0)));
+            settings.push((
+                "enable_linear_filtering",
+                toml::Value::Boolean(values[6] != 0),
+            ));
+            settings.push(("original_mode", toml::Value::Boolean(values[7] != 0)));
+            // Only written by FF8 and FF7 with FFNx
+            if let Some(pause_game_on_background) = values.get(8) {
+                settings.push((
+                    "pause_game_on_background",
+                    toml::Value::Boolean(*pause_game_on_background != 0),
+                ));
+            }
+        }
+        Some(values) => log::warn!(
+            "{video_filename} has {} values instead of at least 8, not importing it",
+            values.len()
+        ),
+        None => {}
+    }
+    match read_cfg_values(&(metadata_path + "\\" + sound_filename), false) {
+        Some(values) if values.len() >= 2 => {
+            settings.push(("sfx_volume", toml::Value::Integer(values[0] as i32 as i64)));
+            settings.push((
+                "music_volume",
+                toml::Value::Integer(values[1] as i32 as i64),
+            ));
         }
+        Some(values) => log::warn!(
+            "{sound_filename} has {} values instead of 2, not importing it",
+            values.len()
+        ),
+        None => {}
+    }
+    settings
+}
+
+fn read_cfg_values(filepath: &str, big_endian: bool) -> Option<Vec<u32>> {
+    let bytes = match std::fs::read(filepath) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::info!("{filepath} not imported: {err}");
+            return None;
+        }
+    };
+    let values = bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            match big_endian {
+                true => u32::from_be_bytes(chunk),
+                false => u32::from_le_bytes(chunk),
+            }
+        })
+        .collect();
+    Some(values)
+}
+
+fn write_file_atomically(filepath: &str, bytes: &[u8]) -> Result<()> {
+    // The game must never read a partially written file, so write aside and then replace it
+    let temp_filepath = filepath.to_string() + ".tmp";
+    std::fs::write(&temp_filepath, bytes)?;
+    std::fs::rename(&temp_filepath, filepath)?;
+    Ok(())
+}
+
+pub fn backup_user_save_dir(ctx: &Context) -> Result<()> {
+    let user_save_dir = get_user_save_dir(ctx)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let backup_dir = Path::new(SAVE_BACKUPS_DIR).join(timestamp.to_string());
+    copy_dir_all(Path::new(&user_save_dir), &backup_dir)?;
+    log::info!("Save directory {user_save_dir} backed up to {backup_dir:?}");
+
+    let mut backups: Vec<(u64, PathBuf)> = std::fs::read_dir(SAVE_BACKUPS_DIR)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            let backup_timestamp = path.file_name()?.to_str()?.parse().ok()?;
+            Some((backup_timestamp, path))
+        })
+        .collect();
+    backups.sort_by_key(|(backup_timestamp, _)| *backup_timestamp);
+    let backups_to_remove = backups
+        .len()
+        .saturating_sub(ctx.config.max_save_backups as usize);
+    for (_, backup_path) in backups.iter().take(backups_to_remove) {
+        log::info!("Removing old save backup {backup_path:?}");
+        std::fs::remove_dir_all(backup_path)?;
+    }
+    Ok(())
+}
+
+fn copy_dir_all(source: &Path, destination: &Path) -> Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let entry_destination = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &entry_destination)?;
+        } else {
+            std::fs::copy(entry.path(), entry_destination)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn get_user_save_dir(ctx: &Context) -> Result<String> {
+    let mut user_save_dir = get_game_metadata_path(ctx)?;
+    if let Some(save_dir) = custom_save_dir(ctx) {
+        user_save_dir = save_dir;
+    } else if std::fs::exists("save").is_ok_and(|v| v) {
+        user_save_dir += "\\save";
     } else {
+        let mut save_profiles: Vec<String> = match Path::new(&user_save_dir).is_dir() {
+            true => std::fs::read_dir(&user_save_dir)?
+                .filter_map(|p| p.ok().map(|p| p.path()))
+                .filter(|p| p.is_dir())
+                .map(|p| {
+                    p.file_name()
+                        .expect("Always have filename")
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .filter(|name| name.starts_with("user_"))
+                .collect(),
+            false => Vec::new(),
+        };
+        save_profiles.sort();
+        user_save_dir += "\\";
+        match select_save_profile(ctx, &save_profiles) {
+            Some(save_profile) => user_save_dir += save_profile,
+            None => {
+                // The game may reject the bare metadata directory, give it a save directory to use
+                match steam_user_id(ctx) {
+                    Some(steam_user_id) => user_save_dir += &format!("user_{steam_user_id}"),
+                    None => user_save_dir += "save",
+                }
+                log::info!("No save directory found, using {user_save_dir}");
+            }
+        }
+    }
+    Ok(user_save_dir)
+}
+
+/// Creates the save directory sent to the game, fresh installs do not have the metadata directory
+/// until the game runs once
+pub fn create_user_save_dir(ctx: &Context) -> Result<()> {
+    let user_save_dir = get_user_save_dir(ctx)?;
+    if !Path::new(&user_save_dir).is_dir() {
+        log::info!("Save directory {user_save_dir} not found, creating it");
+        std::fs::create_dir_all(&user_save_dir)?;
+    }
+    Ok(())
+}
+
+fn select_save_profile<'a>(ctx: &Context, save_profiles: &'a [String]) -> Option<&'a str> {
+    if let Some(save_profile) = &ctx.config.save_profile {
+        if let Some(save_profile) = save_profiles.iter().find(|name| *name == save_profile) {
+            log::info!("Using save profile {save_profile} from save_profile");
+            return Some(save_profile);
+        }
+        log::warn!("save_profile {save_profile} not found among {save_profiles:?}, ignoring it");
+    }
+    if save_profiles.len() > 1 {
+        log::info!(
+            "Multiple save profiles found {:?}, set save_profile to choose one",
+            save_profiles
+        );
+    }
+    save_profiles.last().map(|name| name.as_str())
+}
+
+fn custom_save_dir(ctx: &Context) -> Option<String> {
+    let save_dir = ctx.config.save_dir.as_ref()?;
+    let save_dir_path = Path::new(save_dir);
+    if !save_dir_path.is_absolute() || !save_dir_path.is_dir() {
+        log::warn!("save_dir {save_dir} is not an existing absolute directory, ignoring it");
+        return None;
+    }
+    Some(save_dir.clone())
+}
+
+fn get_install_dir(ctx: &Context) -> Result<PathBuf> {
+    if ctx.config.use_registry_install_dir {
+        if let Some(install_dir) = registry_install_dir(ctx) {
+            return Ok(install_dir);
+        }
+    }
+    Ok(std::path::absolute(".")?)
+}
+
+/// Install dir recorded by Steam in the uninstall registry key of the game
+#[cfg(windows)]
+fn registry_install_dir(ctx: &Context) -> Option<PathBuf> {
+    let uninstall_key = format!(
+        "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Steam App {}",
+        steam_app_id(ctx)
+    );
+    match read_registry_string(HKEY_LOCAL_MACHINE, &uninstall_key, "InstallLocation") {
+        Ok(install_dir) if Path::new(&install_dir).is_dir() => Some(PathBuf::from(install_dir)),
+        Ok(install_dir) => {
+            log::warn!("Registry install dir {install_dir} not found, using the current one");
+            None
+        }
+        Err(err) => {
+            log::warn!("Registry install dir could not be read, using the current one: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn registry_install_dir(_ctx: &Context) -> Option<PathBuf> {
+    log::warn!("No registry to read the install dir from, using the current one");
+    None
+}
+
+pub fn get_game_metadata_path(ctx: &Context) -> Result<String> {
+    // The EStore keeps its metadata in the install dir, store_type also forces it for FF8
+    let is_estore = match ctx.config.store_type {
+        Some(store_type) => store_type == StoreType::EStore,
+        None => {
+            matches!(ctx.game_to_launch, GameType::FF7(StoreType::EStore))
+                || std::fs::exists("data/music_2").is_ok_and(|b| b)
+        }
+    };
+    if is_estore {
         let cwd = std::env::current_dir()?
             .to_str()
             .ok_or(anyhow::anyhow!("cwd cannot be converted to string"))?
             .to_string();
-        game_install_path += &cwd;
+        return Ok(cwd);
     }
+    let game_metadata_dir_name = match ctx.game_to_launch {
+        GameType::FF7(_) => "FINAL FANTASY VII Steam",
+        GameType::FF8 => "FINAL FANTASY VIII Steam",
+    };
+
+    let mut game_install_path = String::new();
+    if let Some(metadata_dir) = &ctx.config.metadata_dir {
+        game_install_path += metadata_dir.trim_end_matches('\\');
+        if Path::new(metadata_dir)
+            .file_name()
+            .is_some_and(|name| name == game_metadata_dir_name)
+        {
+            return Ok(game_install_path);
+        }
+    } else {
+        game_install_path += &documents_dir()?;
+    }
+    game_install_path += "\\Square Enix\\";
+    game_install_path += game_metadata_dir_name;
     Ok(game_install_path)
 }
 
-fn wait_for_game(launcher_ctx: &mut LauncherContext) {
+#[cfg(windows)]
+fn documents_dir() -> Result<String> {
+    let doc_path = unsafe {
+        let doc_path_pw = SHGetKnownFolderPath(&FOLDERID_Documents, KF_FLAG_DEFAULT, None)?;
+        let doc_path = doc_path_pw.to_string()?;
+        CoTaskMemFree(Some(doc_path_pw.as_ptr() as _));
+        doc_path
+    };
+    Ok(doc_path)
+}
+
+#[cfg(not(windows))]
+fn documents_dir() -> Result<String> {
+    Err(anyhow::anyhow!(
+        "The Documents folder is only known on Windows, metadata_dir has to be set"
+    ))
+}
+
+/// Writes a message for the game and waits for the game to read it
+fn send_message(ctx: &Context, channel: &mut dyn GameChannel, bytes: &[u8]) -> Result<()> {
+    channel.send(
+        bytes,
+        Duration::from_secs(ctx.config.handshake_timeout_secs as u64),
+    )
+}
+
+#[cfg(windows)]
+impl GameChannel for LauncherContext {
+    fn write_message(&mut self, bytes: &[u8]) -> Result<()> {
+        write_shared_memory(self.launcher_memory_part, bytes)
+    }
+
+    /// The handshake and the answers to the game requests share the launcher memory part, a
+    /// message is only written once the game read the previous one
+    fn send(&mut self, bytes: &[u8], timeout: Duration) -> Result<()> {
+        let memory_lock = self.memory_lock.clone();
+        let _memory_guard = memory_lock.lock().unwrap();
+        self.write_message(bytes)?;
+        self.wait_for_game(timeout)
+    }
+
+    fn wait_for_game(&mut self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        unsafe { _ = ReleaseSemaphore(self.game_can_read_sem, 1, None) };
+        // Wait in slices so a failed game messages thread is noticed instead of waiting the whole timeout
+        loop {
+            if self.message_thread_failed.load(Ordering::Relaxed) {
+                return Err(anyhow::anyhow!(
+                    "Game messages thread stopped, the game messages cannot be answered anymore"
+                ));
+            }
+            let wait_result = unsafe {
+                WaitForSingleObject(self.game_did_read_sem, GAME_WAIT_SLICE.as_millis() as u32)
+            };
+            match wait_result {
+                WAIT_OBJECT_0 => return Ok(()),
+                WAIT_TIMEOUT if start.elapsed() < timeout => {}
+                WAIT_TIMEOUT => {
+                    log::error!(
+                        "Game did not read the launcher message within {} seconds",
+                        timeout.as_secs()
+                    );
+                    return Err(LauncherError::HandshakeTimeout {
+                        timeout_secs: timeout.as_secs(),
+                    }
+                    .into());
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Waiting for the game to read the launcher message failed: {:?}",
+                        windows::core::Error::from_win32()
+                    ))
+                }
+            }
+        }
+    }
+}
+
+fn write_shared_memory(launcher_memory_part: *mut c_void, bytes: &[u8]) -> Result<()> {
+    let launcher_memory_size = SHARED_MEMORY_SIZE - LAUNCHER_MEMORY_OFFSET;
+    debug_assert!(
+        bytes.len() <= launcher_memory_size,
+        "launcher message of {} bytes overruns the shared memory",
+        bytes.len()
+    );
+    if bytes.len() > launcher_memory_size {
+        return Err(anyhow::anyhow!(
+            "Launcher message of {} bytes does not fit in the {} bytes of shared memory",
+            bytes.len(),
+            launcher_memory_size
+        ));
+    }
     unsafe {
-        // Wait for the game
-        _ = ReleaseSemaphore(launcher_ctx.game_can_read_sem, 1, None);
-        WaitForSingleObject(launcher_ctx.game_did_read_sem, INFINITE);
+        std::ptr::copy(bytes.as_ptr(), launcher_memory_part as _, bytes.len());
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel::MockGameChannel, config::Config};
+
+    fn test_context(game_to_launch: GameType) -> Context {
+        Context {
+            game_to_launch,
+            game_lang: "en".to_string(),
+            use_ffnx: false,
+            config: Config::default(),
+        }
+    }
+
+    fn expected_message(id: u32, payload: &str) -> Vec<u8> {
+        let payload: Vec<u16> = payload.encode_utf16().collect();
+        let mut bytes = id.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend(payload.iter().flat_map(|c| c.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn locale_data_dir_message_layout() {
+        let bytes = locale_data_dir_message(&test_context(GameType::FF7(StoreType::Standard)));
+        assert_eq!(
+            bytes,
+            [
+                13, 0, 0, 0, 7, 0, 0, 0, b'l', 0, b'a', 0, b'n', 0, b'g', 0, b'-', 0, b'e', 0,
+                b'n', 0
+            ]
+        );
+    }
+
+    #[test]
+    fn message_ids_follow_the_game_type() {
+        let ff8_bytes = locale_data_dir_message(&test_context(GameType::FF8));
+        assert_eq!(ff8_bytes, expected_message(FF8_LOCALE_DATA_DIR, "lang-en"));
+        let estore_bytes = locale_data_dir_message(&test_context(GameType::FF7(StoreType::EStore)));
+        assert_eq!(
+            estore_bytes,
+            expected_message(ESTORE_LOCALE_DATA_DIR, "lang-en")
+        );
+    }
+
+    #[test]
+    fn user_doc_dir_message_ends_with_a_zero_byte() {
+        let mut ctx = test_context(GameType::FF7(StoreType::Standard));
+        // Forward slashes split the path on every platform
+        ctx.config.metadata_dir = Some("C:/Games/FINAL FANTASY VII Steam".to_string());
+        let bytes = user_doc_dir_message(&ctx).unwrap();
+        let mut expected_bytes = expected_message(FF7_DOC_DIR, "C:/Games/FINAL FANTASY VII Steam");
+        expected_bytes.push(0);
+        assert_eq!(bytes, expected_bytes);
+    }
+
+    #[test]
+    fn string_message_length_counts_utf16_code_units() {
+        let payload: Vec<u16> = "é🎮".encode_utf16().collect();
+        let bytes = string_message(FF7_INSTALL_DIR, &payload);
+        assert_eq!(bytes[4..8], 3u32.to_le_bytes());
+        assert_eq!(bytes.len(), 8 + 3 * 2);
+    }
+
+    #[test]
+    fn unknown_game_messages_are_acknowledged() {
+        let mut channel = MockGameChannel::default();
+        let payload: Vec<u16> = "request".encode_utf16().collect();
+        answer_game_message(&mut channel, 42, &payload, Duration::from_secs(1)).unwrap();
+        assert_eq!(channel.messages, vec![string_message(42, &[])]);
+        assert_eq!(channel.messages[0], [42, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn game_version_message_layout() {
+        let bytes = game_version_message(&test_context(GameType::FF8));
+        assert_eq!(
+            bytes,
+            expected_message(FF8_GAME_VERSION, &format!("{APP_NAME} {APP_VERSION}"))
+        );
+    }
+
+    #[test]
+    fn id_only_messages_layout() {
+        let ff8_ctx = test_context(GameType::FF8);
+        assert_eq!(disable_cloud_message(&ff8_ctx), Some(vec![21, 0, 0, 0]));
+        assert_eq!(
+            bg_pause_enabled_message(&ff8_ctx),
+            Some(vec![23, 0, 0, 0, 1, 0, 0, 0])
+        );
+        assert_eq!(launcher_completed_message(&ff8_ctx), vec![24, 0, 0, 0]);
+
+        let estore_ctx = test_context(GameType::FF7(StoreType::EStore));
+        assert_eq!(disable_cloud_message(&estore_ctx), None);
+        assert_eq!(bg_pause_enabled_message(&estore_ctx), None);
+        assert_eq!(launcher_completed_message(&estore_ctx), vec![20, 0, 0, 0]);
+    }
+
+    #[test]
+    fn only_install_dirs_close_to_max_path_are_long() {
+        assert!(!is_long_install_dir(LONG_INSTALL_DIR_LEN));
+        assert!(is_long_install_dir(LONG_INSTALL_DIR_LEN + 1));
+        assert!(is_long_install_dir(300));
+    }
+
+    #[test]
+    fn handshake_summary_lists_ids_and_payloads() {
+        let ff8_ctx = test_context(GameType::FF8);
+        let messages = [
+            game_version_message(&ff8_ctx),
+            bg_pause_enabled_message(&ff8_ctx).unwrap(),
+            launcher_completed_message(&ff8_ctx),
+        ];
+        assert_eq!(
+            handshake_summary(&messages),
+            format!("{FF8_GAME_VERSION}=\"{APP_NAME} {APP_VERSION}\", 23=1, 24")
+        );
+    }
+
+    #[test]
+    fn save_profile_selects_the_matching_user_dir() {
+        let profiles = vec!["user_111".to_string(), "user_222".to_string()];
+        let mut ctx = test_context(GameType::FF8);
+        assert_eq!(select_save_profile(&ctx, &profiles), Some("user_222"));
+
+        ctx.config.save_profile = Some("user_111".to_string());
+        assert_eq!(select_save_profile(&ctx, &profiles), Some("user_111"));
+
+        ctx.config.save_profile = Some("user_333".to_string());
+        assert_eq!(select_save_profile(&ctx, &profiles), Some("user_222"));
+        assert_eq!(select_save_profile(&ctx, &[]), None);
+    }
+
+    #[test]
+    fn payload_overrides_replace_string_payloads() {
+        let mut ctx = test_context(GameType::FF8);
+        ctx.config.payload_overrides = vec![(FF8_GAME_VERSION, "custom version".to_string())];
+        assert_eq!(
+            game_version_message(&ctx),
+            expected_message(FF8_GAME_VERSION, "custom version")
+        );
+        assert_eq!(
+            locale_data_dir_message(&ctx),
+            expected_message(FF8_LOCALE_DATA_DIR, "lang-en")
+        );
+    }
+
+    #[test]
+    fn configured_language_overrides_the_executable_one() {
+        let mut ctx = test_context(GameType::FF7(StoreType::Standard));
+        ctx.config.locale_override = Some("ja".to_string());
+        assert_eq!(
+            locale_data_dir_message(&ctx),
+            expected_message(FF7_LOCALE_DATA_DIR, "lang-ja")
+        );
+
+        ctx.config.locale_override = Some("xx".to_string());
+        assert_eq!(
+            locale_data_dir_message(&ctx),
+            expected_message(FF7_LOCALE_DATA_DIR, "lang-en")
+        );
     }
 }
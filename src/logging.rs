@@ -0,0 +1,519 @@
+//! Logger of the launcher, kept in-crate instead of on top of simple_logging or flexi_logger:
+//! logging starts before the config is read, so the level, format, file path and rotation
+//! limits are applied afterwards and the lines of the current launch are moved to the configured
+//! file. It also writes to the Windows event log and to the parent console, which those crates
+//! do not, and keeps the dependency list to anyhow, log, toml and windows.
+
+use std::{
+    ffi::{c_void, CString},
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::{
+    kv::{Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+#[cfg(windows)]
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::HANDLE,
+        Security::PSID,
+        System::{
+            Console::{AllocConsole, AttachConsole, ATTACH_PARENT_PROCESS},
+            EventLog::{
+                RegisterEventSourceA, ReportEventA, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+                EVENTLOG_WARNING_TYPE, REPORT_EVENT_TYPE,
+            },
+            Threading::GetCurrentThreadId,
+        },
+    },
+};
+
+use crate::APP_NAME;
+
+const LOG_LEVEL_ENV: &str = "FF78_LOG";
+pub const DEFAULT_LOG_MAX_FILES: u32 = 3;
+
+static LOGGER: LauncherLogger = LauncherLogger {
+    start: OnceLock::new(),
+    json: AtomicBool::new(false),
+    console: AtomicBool::new(false),
+    sink: Mutex::new(None),
+    event_source: AtomicPtr::new(std::ptr::null_mut()),
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "plain" => Ok(LogFormat::Plain),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid log format {}", value)),
+        }
+    }
+}
+
+struct LauncherLogger {
+    start: OnceLock<Instant>,
+    json: AtomicBool,
+    // Mirrors the log lines to the console opened by --console
+    console: AtomicBool,
+    sink: Mutex<Option<LogFile>>,
+    // Null unless log_to_event_log is enabled
+    event_source: AtomicPtr<c_void>,
+}
+
+impl LauncherLogger {
+    fn format_plain(&self, record: &Record) -> String {
+        let elapsed = self.start.get_or_init(Instant::now).elapsed();
+        let seconds = elapsed.as_secs();
+        format!(
+            "[{:02}:{:02}:{:02}.{:03}] ({:x}) {:6} {}\n",
+            seconds / 3600,
+            (seconds / 60) % 60,
+            seconds % 60,
+            elapsed.subsec_millis(),
+            current_thread_id(),
+            record.level(),
+            record.args()
+        )
+    }
+
+    fn format_json(&self, record: &Record) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+        let mut line = format!(
+            "{{\"timestamp\":{},\"level\":\"{}\",\"message\":\"{}\"",
+            timestamp,
+            record.level(),
+            json_escape(&record.args().to_string())
+        );
+        let mut fields = JsonFieldsVisitor(String::new());
+        _ = record.key_values().visit(&mut fields);
+        if !fields.0.is_empty() {
+            _ = write!(line, ",\"fields\":{{{}}}", fields.0);
+        }
+        line.push_str("}\n");
+        line
+    }
+
+    #[cfg(windows)]
+    fn report_event(&self, event_type: REPORT_EVENT_TYPE, message: &str) {
+        let event_source = self.event_source.load(Ordering::Relaxed);
+        if event_source.is_null() {
+            return;
+        }
+        let Ok(message) = CString::new(message) else {
+            return;
+        };
+        unsafe {
+            _ = ReportEventA(
+                HANDLE(event_source),
+                event_type,
+                0,
+                0,
+                PSID::default(),
+                0,
+                Some(&[PCSTR(message.as_ptr() as _)]),
+                None,
+            );
+        }
+    }
+}
+
+impl Log for LauncherLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let line = match self.json.load(Ordering::Relaxed) {
+            true => self.format_json(record),
+            false => self.format_plain(record),
+        };
+        if self.console.load(Ordering::Relaxed) {
+            _ = std::io::stdout().write_all(line.as_bytes());
+        }
+        if let Some(sink) = self.sink.lock().unwrap().as_mut() {
+            sink.write_line(line.as_bytes());
+        }
+        #[cfg(windows)]
+        match record.level() {
+            Level::Error => self.report_event(EVENTLOG_ERROR_TYPE, &record.args().to_string()),
+            Level::Warn => self.report_event(EVENTLOG_WARNING_TYPE, &record.args().to_string()),
+            _ => {}
+        }
+    }
+
+    fn flush(&self) {
+        if self.console.load(Ordering::Relaxed) {
+            _ = std::io::stdout().flush();
+        }
+        if let Some(sink) = self.sink.lock().unwrap().as_mut() {
+            _ = sink.file.flush();
+        }
+    }
+}
+
+struct JsonFieldsVisitor(String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFieldsVisitor {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        match value.to_u64() {
+            Some(number) => _ = write!(self.0, "\"{}\":{}", json_escape(key.as_str()), number),
+            None => {
+                _ = write!(
+                    self.0,
+                    "\"{}\":\"{}\"",
+                    json_escape(key.as_str()),
+                    json_escape(&value.to_string())
+                )
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => _ = write!(escaped, "\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Log file rotated to `<path>.1`, `<path>.2`... once it grows over max_bytes, 0 never rotates it
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: u32,
+    /// Bytes logged by the previous launches, at the start of the file until they get rotated
+    previous_len: u64,
+}
+
+impl LogFile {
+    /// Appends to the log of the previous launches, it is rotated to `<path>.1` first when max_bytes
+    /// is set. It is kept in place when it cannot be moved, e.g. while another program has it open.
+    fn create(path: &Path, max_bytes: u64, max_files: u32) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let previous_len = file.metadata()?.len();
+        let mut log_file = LogFile {
+            path: path.to_path_buf(),
+            file,
+            written: previous_len,
+            max_bytes,
+            max_files,
+            previous_len,
+        };
+        if max_bytes > 0 {
+            _ = log_file.rotate_previous_log();
+        }
+        Ok(log_file)
+    }
+
+    /// Rotates the log of the previous launches out of the file, keeping the lines of this one
+    fn rotate_previous_log(&mut self) -> std::io::Result<()> {
+        if self.previous_len == 0 {
+            return Ok(());
+        }
+        let contents = std::fs::read(&self.path)?;
+        let launch_lines = contents
+            .get(self.previous_len as usize..)
+            .unwrap_or_default();
+        self.rotate()?;
+        if self.max_files > 0 {
+            // The lines of this launch went along with the previous log, they stay in the new file
+            OpenOptions::new()
+                .write(true)
+                .open(rotated_log_path(&self.path, 1))?
+                .set_len(self.previous_len)?;
+        }
+        self.previous_len = 0;
+        self.file.write_all(launch_lines)?;
+        self.written = launch_lines.len() as u64;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) {
+        if self.max_bytes > 0
+            && self.written > 0
+            && self.written + line.len() as u64 > self.max_bytes
+        {
+            // The logger cannot log its own failures, the line goes to the current file then
+            _ = self.rotate();
+        }
+        if self.file.write_all(line).is_ok() {
+            self.written += line.len() as u64;
+        }
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.max_files > 0 {
+            rotate_log_files(&self.path, self.max_files)?;
+        }
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// Moves the log to `<path>.1`, shifting the rotated ones up to `<path>.<max_files>`
+fn rotate_log_files(path: &Path, max_files: u32) -> std::io::Result<()> {
+    // The oldest file gets replaced by the one before it
+    for index in (1..max_files).rev() {
+        let rotated_path = rotated_log_path(path, index);
+        if rotated_path.exists() {
+            std::fs::rename(&rotated_path, rotated_log_path(path, index + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated_log_path(path, 1))
+}
+
+fn rotated_log_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated_path = path.as_os_str().to_owned();
+    rotated_path.push(format!(".{index}"));
+    PathBuf::from(rotated_path)
+}
+
+#[cfg(windows)]
+fn current_thread_id() -> u32 {
+    unsafe { GetCurrentThreadId() }
+}
+
+// Off Windows only the tests log, from threads the log lines do not need to tell apart
+#[cfg(not(windows))]
+fn current_thread_id() -> u32 {
+    0
+}
+
+/// Logging goes on without a file when it cannot be created, e.g. in a read-only game folder,
+/// until log_path points to a writable one
+#[cfg(windows)]
+pub fn init_logging(path: &str, level: LevelFilter, console: bool) -> Result<()> {
+    if console {
+        unsafe { AllocConsole()? };
+    }
+    LOGGER.start.get_or_init(Instant::now);
+    LOGGER.console.store(console, Ordering::Relaxed);
+    // The rotation limits are only known once the config is read, see apply_config_log_file
+    *LOGGER.sink.lock().unwrap() = LogFile::create(Path::new(path), 0, DEFAULT_LOG_MAX_FILES).ok();
+    log::set_logger(&LOGGER).map_err(|err| anyhow::anyhow!("Logger init failed: {}", err))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Moves the log to log_path, with the lines logged so far, and applies the rotation limits
+pub fn apply_config_log_file(log_path: Option<&str>, max_bytes: u64, max_files: u32) {
+    let current_path = log_file_path();
+    let log_path = log_path.filter(|log_path| current_path.as_deref() != Some(Path::new(log_path)));
+    if let Some(log_path) = log_path {
+        match LogFile::create(Path::new(log_path), max_bytes, max_files) {
+            Ok(log_file) => {
+                let previous_log_file = LOGGER.sink.lock().unwrap().replace(log_file);
+                if let Some(previous_log_file) = previous_log_file {
+                    move_launch_lines(previous_log_file);
+                }
+                log::info!("Logging to {log_path}");
+            }
+            Err(err) => {
+                log::warn!(
+                    "Log file {log_path} could not be created, keeping the current one: {err}"
+                )
+            }
+        }
+    }
+    if let Some(log_file) = LOGGER.sink.lock().unwrap().as_mut() {
+        log_file.max_bytes = max_bytes;
+        log_file.max_files = max_files;
+        if max_bytes > 0 {
+            // The logger cannot log its own failures, the previous log stays in the file then
+            _ = log_file.rotate_previous_log();
+        }
+    }
+}
+
+/// Copies the lines of this launch to the new log file, the old one is left with the lines of
+/// the previous launches
+fn move_launch_lines(log_file: LogFile) {
+    let LogFile {
+        path,
+        file,
+        previous_len,
+        ..
+    } = log_file;
+    drop(file);
+    if let Ok(contents) = std::fs::read(&path) {
+        if let Some(sink) = LOGGER.sink.lock().unwrap().as_mut() {
+            sink.write_line(contents.get(previous_len as usize..).unwrap_or_default());
+        }
+    }
+    let restored = match previous_len {
+        0 => std::fs::remove_file(&path),
+        _ => OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(previous_len)),
+    };
+    if let Err(err) = restored {
+        log::warn!("Removing the lines moved from the log file {path:?} failed: {err}");
+    }
+}
+
+/// Path of the log file currently written, None when no log file could be created
+pub fn log_file_path() -> Option<PathBuf> {
+    LOGGER
+        .sink
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|log_file| log_file.path.clone())
+}
+
+pub fn env_log_level() -> Option<LevelFilter> {
+    std::env::var(LOG_LEVEL_ENV)
+        .ok()
+        .and_then(|value| value.parse().ok())
+}
+
+pub fn apply_config_log_level(config_log_level: LevelFilter) {
+    if env_log_level().is_none() {
+        log::set_max_level(config_log_level);
+    }
+}
+
+pub fn apply_config_log_format(config_log_format: LogFormat) {
+    LOGGER
+        .json
+        .store(config_log_format == LogFormat::Json, Ordering::Relaxed);
+}
+
+#[cfg(windows)]
+pub fn apply_config_event_log(log_to_event_log: bool) {
+    if !log_to_event_log || !LOGGER.event_source.load(Ordering::Relaxed).is_null() {
+        return;
+    }
+    let Ok(source_name) = CString::new(APP_NAME) else {
+        return;
+    };
+    match unsafe { RegisterEventSourceA(None, PCSTR(source_name.as_ptr() as _)) } {
+        Ok(event_source) => LOGGER.event_source.store(event_source.0, Ordering::Relaxed),
+        Err(err) => log::warn!("Registering the event log source failed: {:?}", err),
+    }
+}
+
+/// Reports launch and exit events to the Application Event Log, errors and warnings are
+/// reported by the logger itself
+#[cfg(windows)]
+pub fn report_info_event(message: &str) {
+    LOGGER.report_event(EVENTLOG_INFORMATION_TYPE, message);
+}
+
+#[cfg(windows)]
+pub fn attach_parent_console() {
+    unsafe {
+        _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_file_rotates_over_max_bytes() {
+        let path = std::env::temp_dir().join("ff78launcher_test_rotation.log");
+        let mut log_file = LogFile::create(&path, 10, 2).unwrap();
+        for line in ["first 01\n", "second 2\n", "third 03\n", "fourth 4\n"] {
+            log_file.write_line(line.as_bytes());
+        }
+        drop(log_file);
+
+        let read = |index| std::fs::read_to_string(rotated_log_path(&path, index)).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth 4\n");
+        assert_eq!(read(1), "third 03\n");
+        assert_eq!(read(2), "second 2\n");
+        for index in 1..=2 {
+            std::fs::remove_file(rotated_log_path(&path, index)).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn previous_log_rotation_respects_max_files() {
+        let path = std::env::temp_dir().join("ff78launcher_test_previous.log");
+        std::fs::write(&path, "previous launch\n").unwrap();
+        std::fs::write(rotated_log_path(&path, 1), "older launch\n").unwrap();
+        std::fs::write(rotated_log_path(&path, 2), "oldest launch\n").unwrap();
+        // Like init_logging, the limits are applied once the config is read
+        let mut log_file = LogFile::create(&path, 0, DEFAULT_LOG_MAX_FILES).unwrap();
+        log_file.write_line(b"this launch\n");
+        log_file.max_bytes = 1024;
+        log_file.max_files = 2;
+        log_file.rotate_previous_log().unwrap();
+        log_file.write_line(b"config read\n");
+        drop(log_file);
+
+        let read = |index| std::fs::read_to_string(rotated_log_path(&path, index)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "this launch\nconfig read\n"
+        );
+        assert_eq!(read(1), "previous launch\n");
+        assert_eq!(read(2), "older launch\n");
+        assert!(!rotated_log_path(&path, 3).exists());
+        for index in 1..=2 {
+            std::fs::remove_file(rotated_log_path(&path, index)).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn previous_log_is_appended_to_without_max_bytes() {
+        let path = std::env::temp_dir().join("ff78launcher_test_append.log");
+        std::fs::write(&path, "previous launch\n").unwrap();
+        let mut log_file = LogFile::create(&path, 0, DEFAULT_LOG_MAX_FILES).unwrap();
+        log_file.write_line(b"this launch\n");
+        drop(log_file);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "previous launch\nthis launch\n"
+        );
+        assert!(!rotated_log_path(&path, 1).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
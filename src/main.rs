@@ -1,42 +1,103 @@
 #![windows_subsystem = "windows"]
+// Off Windows only the tests are built, the launch itself is left out with the Win32 modules
+#![cfg_attr(not(windows), allow(dead_code, unused_imports))]
 
+mod channel;
 mod config;
+mod error;
 mod launcher;
+mod logging;
+#[cfg(windows)]
+mod process;
+#[cfg(windows)]
+mod registry;
+#[cfg(windows)]
+mod splash;
+#[cfg(windows)]
+mod tray;
+#[cfg(windows)]
+mod window;
 
 use anyhow::Result;
-use config::Config;
+use channel::{GameChannel, RecordingChannel};
+use config::{
+    default_config_table, reset_config_file, write_imported_config_file, Config, DisplayMode,
+    HandshakeStep,
+};
+use error::LauncherError;
 use launcher::{
-    send_bg_pause_enabled, send_disable_cloud, send_game_version, send_install_dir,
-    send_launcher_completed, send_locale_data_dir, send_user_doc_dir, send_user_save_dir,
-    write_ffsound, write_ffvideo,
+    answer_game_message, backup_user_save_dir, bg_pause_enabled_message, create_user_save_dir,
+    disable_cloud_message, game_version_message, get_game_metadata_path, get_user_save_dir,
+    handshake_summary, install_dir_message, launcher_completed_message, locale_data_dir_message,
+    read_game_message, read_previous_settings, send_bg_pause_enabled, send_disable_cloud,
+    send_game_version, send_install_dir, send_launcher_completed, send_locale_data_dir,
+    send_user_doc_dir, send_user_save_dir, user_doc_dir_message, user_save_dir_message,
+    write_ffsound, write_ffvideo, write_input_cfg,
 };
 use log::LevelFilter;
+#[cfg(windows)]
+use logging::{apply_config_event_log, attach_parent_console, init_logging, report_info_event};
+use logging::{
+    apply_config_log_file, apply_config_log_format, apply_config_log_level, env_log_level,
+};
+#[cfg(windows)]
+use process::{
+    acquire_launcher_instance, check_existing_instance, previous_session_unclean, report_game_exit,
+    run_launch_hook, run_on_launch_command, spawn_game, write_launcher_lock,
+};
+#[cfg(windows)]
+use splash::{close_splash_on_game_window, set_splash_status, show_splash};
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle;
 use std::{
-    ffi::{c_void, CString},
-    os::windows::fs::MetadataExt,
-    process::Command,
-    sync::mpsc::{Receiver, TryRecvError},
+    ffi::{c_char, c_void, CStr, CString},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+#[cfg(windows)]
+use tray::show_tray_icon;
+#[cfg(windows)]
+use window::{make_game_window_borderless, move_game_window, start_window_watchdog};
+#[cfg(windows)]
 use windows::{
     core::{s, PCSTR},
     Win32::{
-        Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
+        Foundation::{
+            CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, INVALID_HANDLE_VALUE,
+        },
         System::{
             Diagnostics::Debug::{
-                SetUnhandledExceptionFilter, EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_POINTERS,
+                MiniDumpNormal, MiniDumpWriteDump, SetUnhandledExceptionFilter,
+                EXCEPTION_EXECUTE_HANDLER, EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
             },
+            LibraryLoader::{GetModuleHandleA, GetProcAddress},
             Memory::{
                 CreateFileMappingA, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS,
                 PAGE_READWRITE,
             },
-            Threading::{CreateSemaphoreA, ReleaseSemaphore, WaitForSingleObject, INFINITE},
+            Threading::{
+                CreateSemaphoreA, GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId,
+                ReleaseSemaphore, WaitForSingleObject, INFINITE,
+            },
+        },
+        UI::WindowsAndMessaging::{
+            MessageBoxA, IDYES, MB_ICONERROR, MB_ICONWARNING, MB_OK, MB_YESNO,
         },
-        UI::WindowsAndMessaging::{MessageBoxA, MB_ICONERROR, MB_OK},
     },
 };
 
 const APP_NAME: &str = "FF78Launcher";
+const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 const LOG_FILE: &str = "FF78Launcher.log";
+const LOCK_FILE: &str = "FF78Launcher.lock";
 const PROCESSES: [&str; 11] = [
     // FF7
     "ff7_de.exe",
@@ -52,27 +113,75 @@ const PROCESSES: [&str; 11] = [
     "ff8_it.exe",
     "ff8_ja.exe",
 ];
+const CONFIG_ARG: &str = "--config";
+const CONSOLE_ARG: &str = "--console";
+const DETECT_ARG: &str = "--detect";
+const DRY_RUN_ARG: &str = "--dry-run";
+const GAME_ARG: &str = "--game";
+const GAME_ARGS_SEPARATOR: &str = "--";
+const IMPORT_SETTINGS_ARG: &str = "--import-settings";
+const NO_EXCEPTION_HANDLER_ARG: &str = "--no-exception-handler";
+const RESET_CONFIG_ARG: &str = "--reset-config";
+const VERSION_ARG: &str = "--version";
 const AF3DN_FILE: &str = "AF3DN.P";
+const FFNX_MIN_FILE_SIZE: u64 = 1024 * 1024;
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const LAUNCHER_MACHINE: u16 = match cfg!(target_pointer_width = "64") {
+    true => IMAGE_FILE_MACHINE_AMD64,
+    false => IMAGE_FILE_MACHINE_I386,
+};
 const GAME_CAN_READ_MSG_SEM: &str = "_gameCanReadMsgSem";
 const GAME_DID_READ_MSG_SEM: &str = "_gameDidReadMsgSem";
 const LAUNCHER_CAN_READ_MSG_SEM: &str = "_launcherCanReadMsgSem";
 const LAUNCHER_DID_READ_MSG_SEM: &str = "_launcherDidReadMsgSem";
 const SHARED_MEMORY_WITH_LAUNCHER_NAME: &str = "_sharedMemoryWithLauncher";
+// The first half of the shared memory is written by the game, the second half by the launcher
+const SHARED_MEMORY_SIZE: usize = 0x20000;
+const LAUNCHER_MEMORY_OFFSET: usize = 0x10000;
+
+// Continuing execution would re-run the faulting instruction and fault again forever, executing
+// the handler ends the process once the crash is logged and dumped
+#[cfg(windows)]
+const CRASH_DISPOSITION: i32 = EXCEPTION_EXECUTE_HANDLER;
 
+#[cfg(windows)]
 static mut HAD_EXCEPTION: bool = false;
+// Read from the config as soon as it is loaded, errors happening before still show the dialog
+static SHOW_ERROR_DIALOG: AtomicBool = AtomicBool::new(true);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum StoreType {
     Standard,
     EStore,
 }
 
-#[derive(Debug)]
+impl FromStr for StoreType {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "standard" => Ok(StoreType::Standard),
+            "estore" => Ok(StoreType::EStore),
+            _ => Err(anyhow::anyhow!("Invalid store type {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum GameType {
     FF7(StoreType),
     FF8,
 }
 
+#[derive(Debug, PartialEq)]
+enum Af3dnDriver {
+    Missing,
+    Invalid,
+    Original,
+    Ffnx,
+}
+
 #[derive(Debug)]
 pub struct Context {
     game_to_launch: GameType,
@@ -81,25 +190,97 @@ pub struct Context {
     config: Config,
 }
 
+#[derive(Debug, Default)]
+struct Args {
+    config: Option<String>,
+    console: bool,
+    detect: bool,
+    dry_run: bool,
+    game: Option<String>,
+    game_args: Vec<String>,
+    import_settings: bool,
+    no_exception_handler: bool,
+    reset_config: bool,
+    version: bool,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Args::default();
+        let mut raw_args = std::env::args().skip(1);
+        while let Some(arg) = raw_args.next() {
+            match arg.as_str() {
+                CONFIG_ARG => args.config = raw_args.next(),
+                CONSOLE_ARG => args.console = true,
+                DETECT_ARG => args.detect = true,
+                DRY_RUN_ARG => args.dry_run = true,
+                GAME_ARG => args.game = raw_args.next(),
+                IMPORT_SETTINGS_ARG => args.import_settings = true,
+                NO_EXCEPTION_HANDLER_ARG => args.no_exception_handler = true,
+                RESET_CONFIG_ARG => args.reset_config = true,
+                VERSION_ARG => args.version = true,
+                GAME_ARGS_SEPARATOR => args.game_args = raw_args.by_ref().collect(),
+                _ => {}
+            }
+        }
+        args
+    }
+}
+
+#[cfg(windows)]
 #[derive(Debug)]
 pub struct LauncherContext {
     game_can_read_sem: HANDLE,
     game_did_read_sem: HANDLE,
     launcher_memory_part: *mut c_void,
+    message_thread_failed: Arc<AtomicBool>,
+    /// Held while a message waits in the launcher memory part for the game to read it
+    memory_lock: Arc<Mutex<()>>,
 }
 
+#[cfg(not(windows))]
 fn main() -> Result<()> {
-    simple_logging::log_to_file(LOG_FILE, LevelFilter::Info)?;
-    log::info!("{APP_NAME} launched!");
+    Err(anyhow::anyhow!(
+        "{APP_NAME} only launches the games on Windows"
+    ))
+}
 
-    unsafe {
-        SetUnhandledExceptionFilter(Some(exception_handler));
-    };
+#[cfg(windows)]
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.version || args.detect {
+        attach_parent_console();
+    }
+    if args.version {
+        println!("{APP_NAME} {APP_VERSION}");
+    }
 
-    match launch_process() {
+    init_logging(
+        LOG_FILE,
+        env_log_level().unwrap_or(LevelFilter::Info),
+        args.console,
+    )?;
+    log::info!("{APP_NAME} {APP_VERSION} launched!");
+
+    if args.no_exception_handler {
+        log::info!("Unhandled exception filter not installed due to {NO_EXCEPTION_HANDLER_ARG}");
+    } else {
+        unsafe {
+            SetUnhandledExceptionFilter(Some(exception_handler));
+        };
+    }
+
+    match launch_process(&args) {
         Ok(_) => Ok(()),
         Err(err) => {
             log::error!("Launching process failed due: {:?}", err);
+            if !SHOW_ERROR_DIALOG.load(Ordering::Relaxed) {
+                return Err(err);
+            }
+            if let Some(launcher_error) = err.downcast_ref::<LauncherError>() {
+                show_launcher_error_message(launcher_error);
+                return Err(err);
+            }
             unsafe {
                 _ = MessageBoxA(
                     None,
@@ -113,81 +294,214 @@ fn main() -> Result<()> {
     }
 }
 
-fn launch_process() -> Result<()> {
-    let processes_available: Vec<&str> = PROCESSES
-        .into_iter()
-        .filter(|process| matches!(std::fs::exists(process), Ok(true)))
-        .collect();
-    if processes_available.len() > 1 {
-        return Err(anyhow::anyhow!(
-            "More than one process to start found: {:?}",
-            processes_available
-        ));
+#[cfg(windows)]
+fn launch_process(args: &Args) -> Result<()> {
+    let previous_session_unclean = previous_session_unclean();
+    let default_config_path = APP_NAME.to_string() + ".toml";
+    if args.reset_config {
+        let config_path = args.config.as_deref().unwrap_or(&default_config_path);
+        match confirm_reset_config(config_path) {
+            true => reset_config_file(config_path)?,
+            false => log::info!("Config reset of {config_path} cancelled"),
+        }
     }
-    let Some(mut process_to_start) = processes_available.first().map(|s| s.to_string()) else {
-        return Err(anyhow::anyhow!("No process to start found!"));
+    // Only a config file about to be generated gets the previous settings
+    let import_settings = args.import_settings
+        && args.config.is_none()
+        && !matches!(std::fs::exists(&default_config_path), Ok(true));
+    let mut config_table = match &args.config {
+        Some(config_path) => {
+            if !matches!(std::fs::exists(config_path), Ok(true)) {
+                return Err(LauncherError::ConfigNotFound(config_path.clone()).into());
+            }
+            log::info!("Using config file {config_path}");
+            read_config_table_or_defaults(config_path)?
+        }
+        None => read_config_table_or_defaults(&default_config_path)?,
     };
+    SHOW_ERROR_DIALOG.store(Config::show_error_dialog(&config_table), Ordering::Relaxed);
+    let _splash = show_splash(Config::show_splash(&config_table));
+    set_splash_status("Detecting game...");
 
-    let game_to_launch = match &process_to_start {
-        name if name.starts_with("ff8") => GameType::FF8,
-        name if name.starts_with("ff7_ja")
-            && std::fs::metadata(AF3DN_FILE)
-                .is_ok_and(|metadata| metadata.file_size() < 1024 * 1024) =>
-        {
-            GameType::FF7(StoreType::EStore)
+    let mut process_to_start = match Config::game_executable(&config_table) {
+        Some(game_executable) => {
+            if !matches!(std::fs::exists(&game_executable), Ok(true)) {
+                return Err(LauncherError::GameExecutableNotFound(game_executable).into());
+            }
+            PathBuf::from(game_executable)
+        }
+        None => {
+            let mut processes_available = find_processes_available()?;
+            let preferred_game = args
+                .game
+                .clone()
+                .or_else(|| Config::preferred_game(&config_table));
+            if let Some(preferred_game) = preferred_game {
+                if preferred_game != "ff7" && preferred_game != "ff8" {
+                    return Err(anyhow::anyhow!(
+                        "Invalid preferred game {}, expected ff7 or ff8",
+                        preferred_game
+                    ));
+                }
+                processes_available
+                    .retain(|process| process.to_lowercase().starts_with(&preferred_game));
+            }
+            if processes_available.len() > 1 {
+                return Err(LauncherError::MultipleProcessesFound(processes_available).into());
+            }
+            let Some(process) = processes_available.first() else {
+                return Err(LauncherError::NoProcessFound.into());
+            };
+            PathBuf::from(process)
         }
-        _ => GameType::FF7(StoreType::Standard),
     };
+    let process_name = process_to_start
+        .file_name()
+        .ok_or(anyhow::anyhow!("Filename of process not found"))?
+        .to_string_lossy()
+        .to_lowercase();
 
-    let use_ffnx =
-        std::fs::metadata(AF3DN_FILE).is_ok_and(|metadata| metadata.file_size() > 1024 * 1024);
-    let game_lang = process_to_start
-        .split('_')
-        .take(2)
-        .last()
-        .map(|end| end.trim_end_matches(".exe").to_string());
-    let Some(game_lang) = game_lang else {
-        return Err(anyhow::anyhow!(
-            "No language found for process: {}",
-            process_to_start
-        ));
-    };
+    let af3dn_driver = detect_af3dn_driver(&af3dn_path(&process_to_start));
+    let (game_to_launch, game_lang, ffnx_detected) = detect_game(&process_name, &af3dn_driver)?;
+    if import_settings {
+        config_table = import_previous_settings(&default_config_path, &game_to_launch, &game_lang)?;
+    }
 
-    let config = Config::from_config_file(&(APP_NAME.to_string() + ".toml"), &game_to_launch)?;
+    let mut config = Config::from_config_table(&config_table, &game_to_launch)?;
+    if is_chocobo_process_name(&process_name) {
+        config.launch_chocobo = true;
+    }
+    config.game_args.extend(args.game_args.iter().cloned());
+    apply_config_log_level(config.log_level);
+    apply_config_log_file(
+        config.log_path.as_deref(),
+        config.log_max_bytes,
+        config.log_max_files,
+    );
+    apply_config_log_format(config.log_format);
+    apply_config_event_log(config.log_to_event_log);
+    if !config.install_exception_handler && !args.no_exception_handler {
+        log::info!("Unhandled exception filter removed due to install_exception_handler");
+        unsafe {
+            SetUnhandledExceptionFilter(None);
+        };
+    }
     log::info!("config: {:?}", config);
 
     if config.launch_chocobo {
-        process_to_start = format!("chocobo_{}.exe", &game_lang);
+        process_to_start.set_file_name(format!("chocobo_{}.exe", &game_lang));
+    }
+
+    let use_ffnx = match config.force_ffnx {
+        Some(force_ffnx) => {
+            log::info!("FFNx usage forced to {force_ffnx} by force_ffnx");
+            force_ffnx
+        }
+        None => ffnx_detected,
+    };
+    disable_ffnx_only_settings(&mut config, &game_to_launch, use_ffnx);
+
+    let game_to_launch = apply_store_type(game_to_launch, config.store_type);
+
+    if let Some(wine_version) = detect_wine() {
+        log::warn!(
+            "Running under Wine {wine_version} (Proton included), the handshake timing may differ from Windows"
+        );
+        if config.wine_startup_delay_ms > config.startup_delay_ms {
+            log::info!(
+                "Startup delay raised to {} ms by wine_startup_delay_ms",
+                config.wine_startup_delay_ms
+            );
+            config.startup_delay_ms = config.wine_startup_delay_ms;
+        }
     }
 
     let ctx = Context {
         game_to_launch,
-        game_lang: game_lang.to_string(),
+        game_lang,
         use_ffnx,
         config,
     };
+    set_splash_status("Configuring...");
+
+    let process_path = std::path::absolute(&process_to_start).map_err(|err| {
+        anyhow::anyhow!(
+            "Resolving the game executable path {:?} failed: {}",
+            process_to_start,
+            err
+        )
+    })?;
+    let process_path = strip_verbatim_prefix(process_path);
+    if args.version {
+        let (game_type, store_type) = game_and_store_type(&ctx);
+        let game_info = format!(
+            "Detected game: {game_type}, store type: {store_type}, FFNx: {}",
+            ctx.use_ffnx
+        );
+        println!("{game_info}");
+        log::info!("{game_info}");
+        return Ok(());
+    }
+    if args.detect {
+        return print_detection(&ctx, &process_path, &af3dn_driver);
+    }
+    // FFNx launches do not use the handshake, a different architecture does not matter then
+    if !ctx.use_ffnx || ctx.config.launch_chocobo {
+        check_game_architecture(&process_path)?;
+    }
+    if args.dry_run {
+        return log_dry_run(&ctx, &process_path);
+    }
+
+    if !acquire_launcher_instance(&ctx)? {
+        log::info!("Launch aborted, another launcher instance is running");
+        return Ok(());
+    }
+    if !check_existing_instance(&ctx, &process_path)? {
+        log::info!("Launch aborted, a game instance is already running");
+        return Ok(());
+    }
+    let _launcher_lock = write_launcher_lock()
+        .inspect_err(|err| log::warn!("Writing {LOCK_FILE} failed: {:?}", err))
+        .ok();
+    run_launch_hook(&ctx, "pre_launch", ctx.config.pre_launch_command.as_ref())?;
+    if ctx.config.backup_saves {
+        if let Err(err) = backup_user_save_dir(&ctx) {
+            log::error!("Backing up the save directory failed: {:?}", err);
+        }
+    }
 
-    let process_filename = std::fs::canonicalize(&process_to_start)?
-        .file_name()
-        .ok_or(anyhow::anyhow!("Filename of process not found"))?
-        .to_os_string();
     if !ctx.use_ffnx || ctx.config.launch_chocobo {
         log::info!(
             "Launching process {:?} without FFNx context: {:?}",
-            process_filename,
+            process_path,
             &ctx
         );
-        if !use_ffnx {
-            write_ffvideo(&ctx)?;
-            write_ffsound(&ctx)?;
+        // The cfg files are written to the metadata directory, missing on the first launch
+        if let Err(err) = create_user_save_dir(&ctx) {
+            log::warn!("Creating the save directory failed: {:?}", err);
         }
-        let name_prefix = match ctx.config.launch_chocobo {
-            true => "choco",
-            false => match ctx.game_to_launch {
-                GameType::FF7(_) => "ff7",
-                GameType::FF8 => "ff8",
-            },
+        if !ctx.use_ffnx {
+            if ctx.config.manage_video_cfg {
+                write_ffvideo(&ctx)?;
+            }
+            if ctx.config.manage_sound_cfg {
+                write_ffsound(&ctx)?;
+            }
+        }
+        write_input_cfg(&ctx)?;
+        let name_prefix = match &ctx.config.semaphore_prefix {
+            Some(semaphore_prefix) => semaphore_prefix.clone(),
+            None => match ctx.config.launch_chocobo {
+                true => "choco",
+                false => match ctx.game_to_launch {
+                    GameType::FF7(_) => "ff7",
+                    GameType::FF8 => "ff8",
+                },
+            }
+            .to_string(),
         };
+        log::info!("Shared memory and semaphore name prefix: {name_prefix}");
         let game_can_read_name = CString::new(name_prefix.to_owned() + GAME_CAN_READ_MSG_SEM)?;
         let game_did_read_name = CString::new(name_prefix.to_owned() + GAME_DID_READ_MSG_SEM)?;
         let shared_memory_name =
@@ -202,83 +516,733 @@ fn launch_process() -> Result<()> {
                 None,
                 PAGE_READWRITE,
                 0,
-                0x20000,
+                SHARED_MEMORY_SIZE as u32,
                 PCSTR(shared_memory_name.as_ptr() as _),
             )?
         };
+        let shared_memory_existed = unsafe { GetLastError() } == ERROR_ALREADY_EXISTS;
+        if shared_memory_existed {
+            log::warn!(
+                "Shared memory {:?} already exists, a stale session from a previous run may be present",
+                shared_memory_name
+            );
+        }
         let view_shared_memory =
             unsafe { MapViewOfFile(shared_memory, FILE_MAP_ALL_ACCESS, 0, 0, 0) };
-        let launcher_memory_part = unsafe { view_shared_memory.Value.offset(0x10000) };
+        if view_shared_memory.Value.is_null() {
+            let map_error = windows::core::Error::from_win32();
+            unsafe {
+                _ = CloseHandle(shared_memory);
+                _ = CloseHandle(game_did_read_sem);
+                _ = CloseHandle(game_can_read_sem);
+            }
+            return Err(LauncherError::SharedMemoryMappingFailed {
+                name: shared_memory_name.to_string_lossy().to_string(),
+                error: map_error,
+            }
+            .into());
+        }
+        if shared_memory_existed
+            && (ctx.config.reset_stale_shared_memory || previous_session_unclean)
+        {
+            log::info!("Resetting the stale shared memory");
+            unsafe {
+                std::ptr::write_bytes(view_shared_memory.Value as *mut u8, 0, SHARED_MEMORY_SIZE)
+            };
+        }
+        let launcher_memory_part = unsafe { view_shared_memory.Value.add(LAUNCHER_MEMORY_OFFSET) };
         let mut launcher_context = LauncherContext {
             game_can_read_sem,
             game_did_read_sem,
             launcher_memory_part,
+            message_thread_failed: Arc::new(AtomicBool::new(false)),
+            memory_lock: Arc::new(Mutex::new(())),
         };
 
         let (thread_kill_tx, thread_kill_rx) = std::sync::mpsc::channel::<()>();
+        let message_thread_failed = launcher_context.message_thread_failed.clone();
+        let memory_lock = launcher_context.memory_lock.clone();
+        let response_timeout = Duration::from_secs(ctx.config.handshake_timeout_secs as u64);
+        let thread_name_prefix = name_prefix.clone();
         let process_game_messages_thread = std::thread::spawn(move || {
-            handle_game_messages_thread(name_prefix, thread_kill_rx).unwrap();
+            if let Err(err) = handle_game_messages_thread(
+                &thread_name_prefix,
+                memory_lock,
+                response_timeout,
+                thread_kill_rx,
+            ) {
+                log::error!("Game messages thread failed: {:?}", err);
+                message_thread_failed.store(true, Ordering::Relaxed);
+            }
         });
 
-        let mut output = Command::new(process_filename).spawn()?;
-        log::info!("Process launched (process_id: {})!", output.id());
-
-        send_locale_data_dir(&ctx, &mut launcher_context);
-        send_user_save_dir(&ctx, &mut launcher_context)?;
-        send_user_doc_dir(&ctx, &mut launcher_context)?;
-        send_install_dir(&ctx, &mut launcher_context)?;
-        send_game_version(&ctx, &mut launcher_context);
-        send_disable_cloud(&ctx, &mut launcher_context);
-        send_bg_pause_enabled(&ctx, &mut launcher_context);
-        send_launcher_completed(&ctx, &mut launcher_context);
-
-        _ = output.wait()?;
-        thread_kill_tx.send(())?;
+        let game_result = run_game_with_handshake(&ctx, &process_path, &mut launcher_context);
+        if let Err(err) = thread_kill_tx.send(()) {
+            log::error!("Stopping the game messages thread failed: {:?}", err);
+        }
 
         // Release launcherCanReadSem for game process thread
         let launcher_can_read_name =
             CString::new(name_prefix.to_owned() + LAUNCHER_CAN_READ_MSG_SEM)?;
         let launcher_can_read_sem =
-            unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(launcher_can_read_name.as_ptr() as _))? };
-        unsafe {
-            ReleaseSemaphore(launcher_can_read_sem, 1, None)?;
+            unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(launcher_can_read_name.as_ptr() as _)) };
+        match &launcher_can_read_sem {
+            Ok(launcher_can_read_sem) => unsafe {
+                if let Err(err) = ReleaseSemaphore(*launcher_can_read_sem, 1, None) {
+                    log::error!("Releasing {:?} failed: {:?}", launcher_can_read_name, err);
+                }
+            },
+            Err(err) => log::error!("Opening {:?} failed: {:?}", launcher_can_read_name, err),
         }
 
-        process_game_messages_thread
-            .join()
-            .map_err(|_| anyhow::anyhow!("Process game thread join failed!"))?;
+        if process_game_messages_thread.join().is_err() {
+            log::error!("Process game thread join failed!");
+        }
 
         unsafe {
             _ = UnmapViewOfFile(view_shared_memory);
             _ = CloseHandle(shared_memory);
             _ = CloseHandle(game_did_read_sem);
             _ = CloseHandle(game_can_read_sem);
-            _ = CloseHandle(launcher_can_read_sem);
+            if let Ok(launcher_can_read_sem) = launcher_can_read_sem {
+                _ = CloseHandle(launcher_can_read_sem);
+            }
         }
+        game_result?;
+        run_launch_hook(&ctx, "post_launch", ctx.config.post_launch_command.as_ref())?;
     } else {
         log::info!(
             "Launching process {:?} with FFNx context: {:?}",
-            process_filename,
+            process_path,
             &ctx
         );
-        let mut output = Command::new(process_filename).spawn()?;
+        // The background pause flag of FF7 is only read through FFNx, the cfg is left as is unless
+        // manage_video_cfg_with_ffnx opts in
+        if matches!(ctx.game_to_launch, GameType::FF7(_))
+            && ctx.config.manage_video_cfg
+            && ctx.config.manage_video_cfg_with_ffnx
+        {
+            write_ffvideo(&ctx)?;
+        }
+        write_input_cfg(&ctx)?;
+        set_splash_status("Launching...");
+        let mut output = spawn_game(&ctx, &process_path)?;
         log::info!("Process launched (process_id: {})!", output.id());
-        _ = output.wait()?;
+        close_splash_on_game_window(output.id());
+        report_info_event(&format!("Game launched: {:?}", process_path));
+        start_window_watchdog(&ctx, output.id());
+        run_on_launch_command(&ctx, output.id());
+        let _tray_icon = show_tray_icon(&ctx, output.id());
+        let exit_code = output.wait()?;
+        report_game_exit(&ctx, &process_path, exit_code);
+        run_launch_hook(&ctx, "post_launch", ctx.config.post_launch_command.as_ref())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+/// A config file that is not valid TOML can be skipped for the default values, so a broken edit
+/// does not keep the game from launching
+fn read_config_table_or_defaults(config_path: &str) -> Result<toml::Table> {
+    let err = match Config::read_config_table(config_path) {
+        Ok(table) => return Ok(table),
+        Err(err) => err,
+    };
+    let parse_error = match (
+        err.downcast_ref::<toml::de::Error>(),
+        err.downcast_ref::<std::str::Utf8Error>(),
+    ) {
+        (Some(toml_error), _) => toml_error.to_string(),
+        (None, Some(utf8_error)) => utf8_error.to_string(),
+        (None, None) => return Err(err),
+    };
+    log::error!("Config file {config_path} could not be parsed: {parse_error}");
+    let Ok(message) = CString::new(format!(
+        "{config_path} is not a valid config file:\n{parse_error}\nLaunch the game with the default config?"
+    )) else {
+        return Err(err);
+    };
+    let answer = unsafe {
+        MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            s!("Invalid config"),
+            MB_ICONWARNING | MB_YESNO,
+        )
+    };
+    if answer != IDYES {
+        return Err(LauncherError::ConfigParseFailed {
+            config_path: config_path.to_string(),
+            error: parse_error,
+        }
+        .into());
+    }
+    log::warn!("Continuing with the default config instead of {config_path}");
+    Ok(default_config_table())
+}
+
+#[cfg(windows)]
+fn confirm_reset_config(config_path: &str) -> bool {
+    let Ok(message) = CString::new(format!(
+        "Replace {config_path} with the default config? The current one is kept as {config_path}.bak"
+    )) else {
+        return false;
+    };
+    let answer = unsafe {
+        MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            s!("Reset config"),
+            MB_ICONWARNING | MB_YESNO,
+        )
+    };
+    answer == IDYES
+}
+
+#[cfg(windows)]
+fn show_launcher_error_message(launcher_error: &LauncherError) {
+    let (title, message) = match launcher_error {
+        LauncherError::NoProcessFound => {
+            show_no_process_found_message();
+            return;
+        }
+        LauncherError::MultipleProcessesFound(processes) => (
+            s!("Several games found"),
+            format!(
+                "Several game executables found: {}. Set preferred_game in {APP_NAME}.toml or pass --game ff7 or --game ff8 to choose one",
+                processes.join(", ")
+            ),
+        ),
+        LauncherError::ConfigParseFailed { config_path, .. } => (
+            s!("Invalid config"),
+            format!(
+                "{config_path} is not a valid config file. Fix it or restore the default one with {RESET_CONFIG_ARG}"
+            ),
+        ),
+        LauncherError::ConfigNotFound(_) | LauncherError::GameExecutableNotFound(_) => {
+            (s!("File not found"), launcher_error.to_string())
+        }
+        LauncherError::ArchitectureMismatch { .. } => (s!("Error"), launcher_error.to_string()),
+        LauncherError::SharedMemoryMappingFailed { .. } => (
+            s!("Error"),
+            "The launcher could not share memory with the game. Check the log file for more info"
+                .to_string(),
+        ),
+        LauncherError::HandshakeTimeout { timeout_secs } => (
+            s!("Game not responding"),
+            format!(
+                "The game did not answer the launcher within {timeout_secs} seconds. Raise handshake_timeout_secs in {APP_NAME}.toml if the game is slow to start"
+            ),
+        ),
+    };
+    let Ok(message) = CString::new(message) else {
+        return;
+    };
+    unsafe {
+        _ = MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            title,
+            MB_ICONERROR | MB_OK,
+        );
+    }
+}
+
+#[cfg(windows)]
+fn show_no_process_found_message() {
+    let Ok(message) = CString::new(format!(
+        "No game executable found. {APP_NAME} must be placed in the game folder, next to one of:\n{}",
+        PROCESSES.join(", ")
+    )) else {
+        return;
+    };
+    unsafe {
+        _ = MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            s!("Game not found"),
+            MB_ICONERROR | MB_OK,
+        );
+    }
+}
+
+fn find_processes_available() -> Result<Vec<String>> {
+    let file_names: Vec<String> = std::fs::read_dir(".")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .collect();
+
+    let processes_available: Vec<String> = file_names
+        .iter()
+        .filter(|name| PROCESSES.contains(&name.to_lowercase().as_str()))
+        .cloned()
+        .collect();
+    if !processes_available.is_empty() {
+        return Ok(processes_available);
+    }
+
+    // Fallback for mod distributions and re-releases shipping differently named executables
+    let alternative_processes: Vec<String> = file_names
+        .iter()
+        .filter(|name| is_alternative_process_name(&name.to_lowercase()))
+        .cloned()
+        .collect();
+    if !alternative_processes.is_empty() {
+        return Ok(alternative_processes);
+    }
+
+    // A standalone Chocobo World executable gets launched as FF8 chocobo
+    let chocobo_processes = file_names
+        .into_iter()
+        .filter(|name| is_chocobo_process_name(&name.to_lowercase()))
+        .collect();
+    Ok(chocobo_processes)
+}
+
+/// Detects the game, its language and whether FFNx is installed from the lowercase process name
+fn detect_game(process_name: &str, af3dn_driver: &Af3dnDriver) -> Result<(GameType, String, bool)> {
+    let game_to_launch = match process_name {
+        name if name.starts_with("ff8") || is_chocobo_process_name(name) => GameType::FF8,
+        name if name.starts_with("ff7_ja") && *af3dn_driver == Af3dnDriver::Original => {
+            GameType::FF7(StoreType::EStore)
+        }
+        _ => GameType::FF7(StoreType::Standard),
+    };
+
+    let game_lang = process_name
+        .trim_end_matches(".exe")
+        .split('_')
+        .nth(1)
+        .filter(|lang| !lang.is_empty());
+    let Some(game_lang) = game_lang else {
+        return Err(anyhow::anyhow!(
+            "No language found for process: {}",
+            process_name
+        ));
+    };
+
+    Ok((
+        game_to_launch,
+        game_lang.to_string(),
+        *af3dn_driver == Af3dnDriver::Ffnx,
+    ))
+}
+
+/// Seeds the generated config file with the settings of the original launcher
+fn import_previous_settings(
+    config_path: &str,
+    game_to_launch: &GameType,
+    game_lang: &str,
+) -> Result<toml::Table> {
+    // The metadata directory only depends on the game and the default config at this point
+    let default_ctx = Context {
+        game_to_launch: *game_to_launch,
+        game_lang: game_lang.to_string(),
+        use_ffnx: false,
+        config: Config::default(),
+    };
+    let settings = read_previous_settings(&default_ctx);
+    if settings.is_empty() {
+        log::info!("No previous launcher settings found to import");
+        return Config::read_config_table(config_path);
+    }
+    for (key, value) in &settings {
+        log::info!("Imported previous launcher setting {key} = {value}");
+    }
+    write_imported_config_file(config_path, &settings)?;
+    Config::read_config_table(config_path)
+}
+
+/// Converts `\\?\C:\...` and `\\?\UNC\server\...` paths back to their usual form, the game and
+/// the working directory derived from it do not always handle verbatim paths
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let path_str = path.to_string_lossy().to_string();
+    if let Some(unc_path) = path_str.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{unc_path}"));
+    }
+    match path_str.strip_prefix(r"\\?\") {
+        Some(disk_path) => PathBuf::from(disk_path),
+        None => path,
+    }
+}
+
+fn is_alternative_process_name(name: &str) -> bool {
+    (name.starts_with("ff7") || name.starts_with("ff8"))
+        && name.ends_with(".exe")
+        && !name.contains("launcher")
+}
+
+/// The driver is loaded from the game directory, which is not the cwd when the launcher is run
+/// from elsewhere
+fn af3dn_path(process_path: &Path) -> PathBuf {
+    let af3dn_path = process_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(AF3DN_FILE);
+    match std::path::absolute(&af3dn_path) {
+        Ok(absolute_path) => strip_verbatim_prefix(absolute_path),
+        Err(_) => af3dn_path,
+    }
+}
+
+fn detect_af3dn_driver(af3dn_path: &Path) -> Af3dnDriver {
+    let Ok(mut file) = File::open(af3dn_path) else {
+        log::info!("{AF3DN_FILE} not found at {:?}", af3dn_path);
+        return Af3dnDriver::Missing;
+    };
+    let file_size = file
+        .metadata()
+        .map(|metadata| metadata.len())
+        .unwrap_or_default();
+    let is_pe_file = has_pe_header(&mut file);
+    // Both drivers are DLLs, a file without a PE header is corrupt or partially downloaded
+    let af3dn_driver = match (is_pe_file, file_size > FFNX_MIN_FILE_SIZE) {
+        (false, _) => Af3dnDriver::Invalid,
+        (true, true) => Af3dnDriver::Ffnx,
+        (true, false) => Af3dnDriver::Original,
+    };
+    log::info!(
+        "{AF3DN_FILE} at {:?} detected as {:?} (PE header: {}, size: {} bytes)",
+        af3dn_path,
+        af3dn_driver,
+        is_pe_file,
+        file_size
+    );
+    if af3dn_driver == Af3dnDriver::Invalid {
+        log::warn!("{AF3DN_FILE} is not a valid DLL, it is neither treated as FFNx nor as the eStore driver");
+    }
+    af3dn_driver
+}
+
+fn has_pe_header(file: &mut File) -> bool {
+    read_pe_machine(file).is_some()
+}
+
+/// Machine type of the PE file header, None when the file is not a PE file
+fn read_pe_machine(file: &mut File) -> Option<u16> {
+    let mut dos_header = [0u8; 0x40];
+    if file.read_exact(&mut dos_header).is_err() || &dos_header[..2] != b"MZ" {
+        return None;
+    }
+    let pe_header_offset = u32::from_le_bytes([
+        dos_header[0x3c],
+        dos_header[0x3d],
+        dos_header[0x3e],
+        dos_header[0x3f],
+    ]);
+    // The machine type directly follows the PE signature
+    let mut pe_header = [0u8; 6];
+    if file.seek(SeekFrom::Start(pe_header_offset as u64)).is_err()
+        || file.read_exact(&mut pe_header).is_err()
+        || &pe_header[..4] != b"PE\0\0"
+    {
+        return None;
+    }
+    Some(u16::from_le_bytes([pe_header[4], pe_header[5]]))
+}
+
+#[cfg(windows)]
+/// Returns the Wine version when running under Wine or Proton, their ntdll exports
+/// wine_get_version while the Windows one does not
+fn detect_wine() -> Option<String> {
+    unsafe {
+        let ntdll = GetModuleHandleA(s!("ntdll.dll")).ok()?;
+        let wine_get_version = GetProcAddress(ntdll, s!("wine_get_version"))?;
+        let wine_get_version: unsafe extern "C" fn() -> *const c_char =
+            std::mem::transmute(wine_get_version);
+        let version = wine_get_version();
+        if version.is_null() {
+            return Some("unknown".to_string());
+        }
+        Some(CStr::from_ptr(version).to_string_lossy().to_string())
+    }
+}
+
+/// The shared memory and semaphores are only shared by a launcher and a game of the same
+/// architecture, the handshake silently fails otherwise
+fn check_game_architecture(process_path: &Path) -> Result<()> {
+    let game_machine = File::open(process_path)
+        .ok()
+        .and_then(|mut file| read_pe_machine(&mut file));
+    match game_machine {
+        Some(game_machine) if game_machine != LAUNCHER_MACHINE => {
+            Err(LauncherError::ArchitectureMismatch {
+                process_path: process_path.to_path_buf(),
+                game_machine: machine_name(game_machine),
+                launcher_machine: machine_name(LAUNCHER_MACHINE),
+            }
+            .into())
+        }
+        Some(_) => Ok(()),
+        None => {
+            log::warn!("Architecture of {:?} could not be read", process_path);
+            Ok(())
+        }
+    }
+}
+
+fn machine_name(machine: u16) -> String {
+    match machine {
+        IMAGE_FILE_MACHINE_I386 => "32-bit".to_string(),
+        IMAGE_FILE_MACHINE_AMD64 => "64-bit".to_string(),
+        machine => format!("unknown (0x{machine:x})"),
+    }
+}
+
+fn is_chocobo_process_name(name: &str) -> bool {
+    name.starts_with("chocobo_") && name.ends_with(".exe")
+}
+
+#[cfg(windows)]
+fn run_game_with_handshake(
+    ctx: &Context,
+    process_path: &Path,
+    launcher_ctx: &mut LauncherContext,
+) -> Result<()> {
+    set_splash_status("Launching...");
+    let mut output = spawn_game(ctx, process_path)?;
+    log::info!("Process launched (process_id: {})!", output.id());
+    close_splash_on_game_window(output.id());
+    report_info_event(&format!("Game launched: {:?}", process_path));
+    start_window_watchdog(ctx, output.id());
+    run_on_launch_command(ctx, output.id());
+
+    if ctx.config.startup_delay_ms > 0 {
+        log::info!(
+            "Waiting {} ms before sending the first message",
+            ctx.config.startup_delay_ms
+        );
+        std::thread::sleep(Duration::from_millis(ctx.config.startup_delay_ms as u64));
+    }
+    set_splash_status("Sending the settings to the game...");
+    if let Err(err) = send_handshake(ctx, launcher_ctx) {
+        log::error!("Handshake with the game failed, terminating it: {:?}", err);
+        _ = output.kill();
+        _ = output.wait();
+        return Err(err);
+    }
+    match ctx.config.display_mode {
+        DisplayMode::Windowed => {
+            if let (Some(window_x), Some(window_y)) = (ctx.config.window_x, ctx.config.window_y) {
+                if let Err(err) = move_game_window(output.id(), window_x, window_y) {
+                    log::warn!("Game window could not be moved: {:?}", err);
+                }
+            }
+        }
+        DisplayMode::Borderless => {
+            if let Err(err) = make_game_window_borderless(
+                output.id(),
+                ctx.config.window_x.unwrap_or(0),
+                ctx.config.window_y.unwrap_or(0),
+                ctx.config.window_width,
+                ctx.config.window_height,
+            ) {
+                log::warn!("Game window could not be made borderless: {:?}", err);
+            }
+        }
+        DisplayMode::Fullscreen => {}
+    }
+
+    let _tray_icon = show_tray_icon(ctx, output.id());
+    let exit_code = output.wait()?;
+    report_game_exit(ctx, process_path, exit_code);
+    Ok(())
+}
+
+fn send_handshake(ctx: &Context, channel: &mut dyn GameChannel) -> Result<()> {
+    let mut channel = RecordingChannel::new(channel);
+    for step in &ctx.config.send_order {
+        match step {
+            HandshakeStep::LocaleDataDir => send_locale_data_dir(ctx, &mut channel)?,
+            HandshakeStep::UserSaveDir => send_user_save_dir(ctx, &mut channel)?,
+            HandshakeStep::UserDocDir => send_user_doc_dir(ctx, &mut channel)?,
+            HandshakeStep::InstallDir => send_install_dir(ctx, &mut channel)?,
+            HandshakeStep::GameVersion => send_game_version(ctx, &mut channel)?,
+            HandshakeStep::DisableCloud => send_disable_cloud(ctx, &mut channel)?,
+            HandshakeStep::BgPauseEnabled => send_bg_pause_enabled(ctx, &mut channel)?,
+            HandshakeStep::LauncherCompleted => send_launcher_completed(ctx, &mut channel)?,
+        }
+    }
+    log::info!(
+        "Handshake summary: {}",
+        handshake_summary(&channel.messages)
+    );
+    Ok(())
+}
+
+fn disable_ffnx_only_settings(config: &mut Config, game_to_launch: &GameType, use_ffnx: bool) {
+    // FF7 supports pausing in background only through FFNx
+    if matches!(game_to_launch, GameType::FF7(_)) && !use_ffnx {
+        config.pause_game_on_background = false;
+    }
+}
+
+/// Forces the store type set by store_type over the one detected from the AF3DN.P size
+fn apply_store_type(game_to_launch: GameType, store_type: Option<StoreType>) -> GameType {
+    let Some(store_type) = store_type else {
+        return game_to_launch;
+    };
+    match game_to_launch {
+        GameType::FF7(detected) => {
+            if detected != store_type {
+                log::info!(
+                    "Store type forced to {store_type:?} by store_type (detected {detected:?})"
+                );
+            }
+            GameType::FF7(store_type)
+        }
+        // FF8 has no EStore specific message ids, only its metadata directory differs
+        GameType::FF8 => {
+            log::info!("Store type forced to {store_type:?} by store_type");
+            GameType::FF8
+        }
     }
+}
+
+/// Game name and store type, FF8 only has the store type set in the config
+fn game_and_store_type(ctx: &Context) -> (&'static str, String) {
+    match &ctx.game_to_launch {
+        GameType::FF7(store_type) => ("FF7", format!("{store_type:?}")),
+        GameType::FF8 => (
+            "FF8",
+            ctx.config
+                .store_type
+                .map_or("-".to_string(), |store_type| format!("{store_type:?}")),
+        ),
+    }
+}
 
+#[cfg(windows)]
+fn print_detection(ctx: &Context, process_path: &Path, af3dn_driver: &Af3dnDriver) -> Result<()> {
+    let (game_type, store_type) = game_and_store_type(ctx);
+    let detection = [
+        (
+            "Processes found",
+            format!("{:?}", find_processes_available()?),
+        ),
+        ("Process to launch", format!("{:?}", process_path)),
+        ("Game", game_type.to_string()),
+        ("Store type", store_type),
+        ("Game language", ctx.game_lang.clone()),
+        (
+            "Game architecture",
+            File::open(process_path)
+                .ok()
+                .and_then(|mut file| read_pe_machine(&mut file))
+                .map_or("unknown".to_string(), machine_name),
+        ),
+        ("Launcher architecture", machine_name(LAUNCHER_MACHINE)),
+        ("Wine", detect_wine().unwrap_or("-".to_string())),
+        ("AF3DN.P driver", format!("{af3dn_driver:?}")),
+        ("Use FFNx", ctx.use_ffnx.to_string()),
+        (
+            "Metadata path",
+            get_game_metadata_path(ctx).unwrap_or_else(|err| format!("not found ({err})")),
+        ),
+        (
+            "Save dir",
+            get_user_save_dir(ctx).unwrap_or_else(|err| format!("not found ({err})")),
+        ),
+    ];
+    for (name, value) in detection {
+        println!("{name}: {value}");
+        log::info!("Detect: {name}: {value}");
+    }
     Ok(())
 }
 
-fn handle_game_messages_thread(name_prefix: &str, thread_kill_rx: Receiver<()>) -> Result<()> {
+fn log_dry_run(ctx: &Context, process_path: &Path) -> Result<()> {
+    if ctx.use_ffnx && !ctx.config.launch_chocobo {
+        log::info!(
+            "Dry run: would launch process {:?} with FFNx context: {:?}",
+            process_path,
+            ctx
+        );
+        return Ok(());
+    }
+
+    log::info!(
+        "Dry run: would launch process {:?} without FFNx context: {:?}",
+        process_path,
+        ctx
+    );
+    let mut messages = Vec::new();
+    for step in &ctx.config.send_order {
+        messages.extend(handshake_message(ctx, step)?);
+    }
+    log::info!("Dry run handshake: {}", handshake_summary(&messages));
+    log::info!("Dry run completed, game process not launched");
+    Ok(())
+}
+
+/// Message send_handshake sends for the step, None when the step sends nothing for this game
+fn handshake_message(ctx: &Context, step: &HandshakeStep) -> Result<Option<Vec<u8>>> {
+    let message = match step {
+        HandshakeStep::LocaleDataDir => Some(locale_data_dir_message(ctx)),
+        HandshakeStep::UserSaveDir => Some(user_save_dir_message(ctx)?),
+        HandshakeStep::UserDocDir => Some(user_doc_dir_message(ctx)?),
+        HandshakeStep::InstallDir => Some(install_dir_message(ctx)?),
+        HandshakeStep::GameVersion => Some(game_version_message(ctx)),
+        HandshakeStep::DisableCloud => disable_cloud_message(ctx),
+        HandshakeStep::BgPauseEnabled => bg_pause_enabled_message(ctx),
+        HandshakeStep::LauncherCompleted => Some(launcher_completed_message(ctx)),
+    };
+    Ok(message)
+}
+
+#[cfg(windows)]
+/// Reads the game requests and answers them through the launcher memory part, shared with the
+/// handshake through memory_lock
+fn handle_game_messages_thread(
+    name_prefix: &str,
+    memory_lock: Arc<Mutex<()>>,
+    response_timeout: Duration,
+    thread_kill_rx: Receiver<()>,
+) -> Result<()> {
     log::info!("Starting game message queue thread...");
 
     let launcher_can_read_name = CString::new(name_prefix.to_owned() + LAUNCHER_CAN_READ_MSG_SEM)?;
     let launcher_did_read_name = CString::new(name_prefix.to_owned() + LAUNCHER_DID_READ_MSG_SEM)?;
+    let game_can_read_name = CString::new(name_prefix.to_owned() + GAME_CAN_READ_MSG_SEM)?;
+    let game_did_read_name = CString::new(name_prefix.to_owned() + GAME_DID_READ_MSG_SEM)?;
 
     let launcher_can_read_sem =
         unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(launcher_can_read_name.as_ptr() as _))? };
     let launcher_did_read_sem =
         unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(launcher_did_read_name.as_ptr() as _))? };
+    let game_can_read_sem =
+        unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(game_can_read_name.as_ptr() as _))? };
+    let game_did_read_sem =
+        unsafe { CreateSemaphoreA(None, 0, 1, PCSTR(game_did_read_name.as_ptr() as _))? };
+    let shared_memory_name =
+        CString::new(name_prefix.to_owned() + SHARED_MEMORY_WITH_LAUNCHER_NAME)?;
+    let shared_memory = unsafe {
+        CreateFileMappingA(
+            INVALID_HANDLE_VALUE,
+            None,
+            PAGE_READWRITE,
+            0,
+            SHARED_MEMORY_SIZE as u32,
+            PCSTR(shared_memory_name.as_ptr() as _),
+        )?
+    };
+    let view_shared_memory = unsafe { MapViewOfFile(shared_memory, FILE_MAP_ALL_ACCESS, 0, 0, 0) };
+    let game_memory_part = view_shared_memory.Value as *const c_void;
+    let mut response_channel = LauncherContext {
+        game_can_read_sem,
+        game_did_read_sem,
+        launcher_memory_part: match game_memory_part.is_null() {
+            true => std::ptr::null_mut(),
+            false => unsafe { view_shared_memory.Value.add(LAUNCHER_MEMORY_OFFSET) },
+        },
+        message_thread_failed: Arc::new(AtomicBool::new(false)),
+        memory_lock,
+    };
 
     loop {
         match thread_kill_rx.try_recv() {
@@ -290,10 +1254,32 @@ fn handle_game_messages_thread(name_prefix: &str, thread_kill_rx: Receiver<()>)
 
         log::info!("Game message thread waiting for launcherCanReadSem semaphore...");
         unsafe { WaitForSingleObject(launcher_can_read_sem, INFINITE) };
+        // The semaphore is also released by the launcher itself on shutdown, no message to read then
+        match thread_kill_rx.try_recv() {
+            Ok(_) | Err(TryRecvError::Disconnected) => {
+                break;
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+
+        let game_message =
+            (!game_memory_part.is_null()).then(|| read_game_message(game_memory_part));
         log::info!("Game message thread releasing launcherDidReadSem semaphore...");
         _ = unsafe { ReleaseSemaphore(launcher_did_read_sem, 1, None) };
+        // Answered once the game is free to go on, it may be waiting for launcherDidReadSem first
+        if let Some((id, payload)) = game_message {
+            if let Err(err) =
+                answer_game_message(&mut response_channel, id, &payload, response_timeout)
+            {
+                log::error!("Answering the game message {id} failed: {:?}", err);
+            }
+        }
     }
     unsafe {
+        _ = UnmapViewOfFile(view_shared_memory);
+        _ = CloseHandle(shared_memory);
+        _ = CloseHandle(game_did_read_sem);
+        _ = CloseHandle(game_can_read_sem);
         _ = CloseHandle(launcher_did_read_sem);
         _ = CloseHandle(launcher_can_read_sem);
     }
@@ -301,20 +1287,240 @@ fn handle_game_messages_thread(name_prefix: &str, thread_kill_rx: Receiver<()>)
     Ok(())
 }
 
+#[cfg(windows)]
 unsafe extern "system" fn exception_handler(ep: *const EXCEPTION_POINTERS) -> i32 {
     if HAD_EXCEPTION {
         log::error!("ExceptionHandler: crash while running another Exception Handler. Exiting.");
         SetUnhandledExceptionFilter(None);
-        return EXCEPTION_CONTINUE_EXECUTION;
+        return CRASH_DISPOSITION;
     }
 
     HAD_EXCEPTION = true;
     let exception_record = &*(*ep).ExceptionRecord;
     log::error!(
-        "Exception 0x{:x}, address 0x{:x}",
+        "Exception 0x{:x}, address {:p}",
         exception_record.ExceptionCode.0,
-        exception_record.ExceptionAddress as i32
+        exception_record.ExceptionAddress
     );
+    match write_minidump(ep) {
+        Ok(dump_path) => log::error!("Crash dump written to {dump_path}"),
+        Err(err) => log::error!("Crash dump could not be written: {:?}", err),
+    }
     SetUnhandledExceptionFilter(None);
-    EXCEPTION_CONTINUE_EXECUTION
+    log::logger().flush();
+    CRASH_DISPOSITION
+}
+
+#[cfg(windows)]
+fn write_minidump(ep: *const EXCEPTION_POINTERS) -> Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let dump_path = format!("{APP_NAME}_{timestamp}.dmp");
+    let dump_file = std::fs::File::create(&dump_path)?;
+    let exception_information = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: unsafe { GetCurrentThreadId() },
+        ExceptionPointers: ep as *mut _,
+        ClientPointers: false.into(),
+    };
+    unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            HANDLE(dump_file.as_raw_handle()),
+            MiniDumpNormal,
+            Some(&exception_information as *const _),
+            None,
+            None,
+        )?;
+    }
+    Ok(dump_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::MockGameChannel;
+
+    #[test]
+    fn ff7_ja_with_original_driver_is_estore() {
+        let (game, lang, ffnx) = detect_game("ff7_ja.exe", &Af3dnDriver::Original).unwrap();
+        assert_eq!(game, GameType::FF7(StoreType::EStore));
+        assert_eq!(lang, "ja");
+        assert!(!ffnx);
+    }
+
+    #[test]
+    fn ff7_ja_with_ffnx_is_standard() {
+        let (game, lang, ffnx) = detect_game("ff7_ja.exe", &Af3dnDriver::Ffnx).unwrap();
+        assert_eq!(game, GameType::FF7(StoreType::Standard));
+        assert_eq!(lang, "ja");
+        assert!(ffnx);
+    }
+
+    #[test]
+    fn ff7_other_languages_are_standard() {
+        let (game, lang, _) = detect_game("ff7_en.exe", &Af3dnDriver::Original).unwrap();
+        assert_eq!(game, GameType::FF7(StoreType::Standard));
+        assert_eq!(lang, "en");
+    }
+
+    #[test]
+    fn ff7_pauses_in_background_only_through_ffnx() {
+        let ff7 = GameType::FF7(StoreType::Standard);
+        for (game_to_launch, use_ffnx, expected_pause) in [
+            (ff7, false, false),
+            (ff7, true, true),
+            (GameType::FF8, false, true),
+        ] {
+            let mut config = Config {
+                pause_game_on_background: true,
+                ..Default::default()
+            };
+            disable_ffnx_only_settings(&mut config, &game_to_launch, use_ffnx);
+            assert_eq!(config.pause_game_on_background, expected_pause);
+        }
+    }
+
+    #[test]
+    fn store_type_overrides_the_detected_one() {
+        let (game, _, _) = detect_game("ff7_de.exe", &Af3dnDriver::Original).unwrap();
+        assert_eq!(
+            apply_store_type(game, Some(StoreType::EStore)),
+            GameType::FF7(StoreType::EStore)
+        );
+        assert_eq!(apply_store_type(game, None), game);
+        assert_eq!(
+            apply_store_type(GameType::FF8, Some(StoreType::EStore)),
+            GameType::FF8
+        );
+    }
+
+    #[test]
+    fn ff8_and_chocobo_are_detected() {
+        let (game, lang, _) = detect_game("ff8_fr.exe", &Af3dnDriver::Missing).unwrap();
+        assert_eq!(game, GameType::FF8);
+        assert_eq!(lang, "fr");
+
+        let (game, lang, _) = detect_game("chocobo_de.exe", &Af3dnDriver::Missing).unwrap();
+        assert_eq!(game, GameType::FF8);
+        assert_eq!(lang, "de");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn crashes_terminate_instead_of_resuming_the_faulting_instruction() {
+        // EXCEPTION_CONTINUE_EXECUTION spins on access violations since the fault is never fixed
+        assert_ne!(
+            CRASH_DISPOSITION,
+            windows::Win32::System::Diagnostics::Debug::EXCEPTION_CONTINUE_EXECUTION
+        );
+        assert_eq!(CRASH_DISPOSITION, EXCEPTION_EXECUTE_HANDLER);
+    }
+
+    #[test]
+    fn verbatim_prefixes_are_stripped() {
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\C:\Games\ff7_en.exe")),
+            PathBuf::from(r"C:\Games\ff7_en.exe")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\UNC\nas\games\ff7_en.exe")),
+            PathBuf::from(r"\\nas\games\ff7_en.exe")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"Z:\ff7_en.exe")),
+            PathBuf::from(r"Z:\ff7_en.exe")
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn af3dn_is_resolved_next_to_the_executable() {
+        assert_eq!(
+            af3dn_path(Path::new(r"C:\Games\FF7\ff7_en.exe")),
+            PathBuf::from(r"C:\Games\FF7\AF3DN.P")
+        );
+        assert_eq!(
+            af3dn_path(Path::new("ff7_en.exe")),
+            std::env::current_dir().unwrap().join(AF3DN_FILE)
+        );
+    }
+
+    #[test]
+    fn handshake_sends_every_message_in_order() {
+        let test_dir = std::env::temp_dir().join("ff78launcher_test_handshake");
+        let ctx = Context {
+            game_to_launch: GameType::FF8,
+            game_lang: "en".to_string(),
+            use_ffnx: false,
+            config: Config {
+                metadata_dir: Some(test_dir.to_string_lossy().to_string()),
+                store_type: Some(StoreType::Standard),
+                ..Default::default()
+            },
+        };
+        let mut channel = MockGameChannel::default();
+        send_handshake(&ctx, &mut channel).unwrap();
+        // Only the launch creates the save directory, the handshake just resolves it
+        assert!(!test_dir.exists());
+
+        let ids: Vec<u32> = channel
+            .messages
+            .iter()
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(ids, [12, 9, 10, 11, 17, 21, 23, 24]);
+    }
+
+    #[test]
+    fn dry_run_messages_follow_the_send_order() {
+        let ctx = Context {
+            game_to_launch: GameType::FF8,
+            game_lang: "en".to_string(),
+            use_ffnx: false,
+            config: Config {
+                store_type: Some(StoreType::EStore),
+                send_order: vec![
+                    HandshakeStep::GameVersion,
+                    HandshakeStep::InstallDir,
+                    HandshakeStep::LauncherCompleted,
+                ],
+                ..Default::default()
+            },
+        };
+        let mut channel = MockGameChannel::default();
+        send_handshake(&ctx, &mut channel).unwrap();
+
+        let messages: Vec<Vec<u8>> = ctx
+            .config
+            .send_order
+            .iter()
+            .filter_map(|step| handshake_message(&ctx, step).unwrap())
+            .collect();
+        assert_eq!(messages, channel.messages);
+    }
+
+    #[test]
+    fn pe_machine_is_read_after_the_signature() {
+        let path = std::env::temp_dir().join("ff78launcher_test_machine.exe");
+        let mut pe_file = vec![0u8; 0x40];
+        pe_file[..2].copy_from_slice(b"MZ");
+        pe_file[0x3c] = 0x40;
+        pe_file.extend_from_slice(b"PE\0\0");
+        pe_file.extend_from_slice(&IMAGE_FILE_MACHINE_I386.to_le_bytes());
+        std::fs::write(&path, &pe_file).unwrap();
+        let machine = read_pe_machine(&mut File::open(&path).unwrap());
+
+        std::fs::write(&path, b"not a PE file").unwrap();
+        let invalid_machine = read_pe_machine(&mut File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(machine, Some(IMAGE_FILE_MACHINE_I386));
+        assert_eq!(invalid_machine, None);
+    }
+
+    #[test]
+    fn names_without_language_are_rejected() {
+        assert!(detect_game("ff7.exe", &Af3dnDriver::Missing).is_err());
+        assert!(detect_game("ff8_.exe", &Af3dnDriver::Missing).is_err());
+    }
 }
@@ -0,0 +1,725 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    os::windows::{ffi::OsStrExt, io::AsRawHandle},
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use windows::{
+    core::{s, HRESULT, PCSTR, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, BOOL, ERROR_ACCESS_DENIED, ERROR_ALREADY_EXISTS,
+            ERROR_SHARING_VIOLATION, HANDLE, STILL_ACTIVE, WIN32_ERROR,
+        },
+        System::{
+            Diagnostics::Debug::CheckRemoteDebuggerPresent,
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            Registry::HKEY_CURRENT_USER,
+            Threading::{
+                CreateMutexA, CreateProcessW, GetCurrentProcess, GetExitCodeProcess,
+                GetProcessAffinityMask, OpenProcess, ResumeThread, SetPriorityClass,
+                SetProcessAffinityMask, TerminateProcess, WaitForSingleObject,
+                ABOVE_NORMAL_PRIORITY_CLASS, CREATE_SUSPENDED, CREATE_UNICODE_ENVIRONMENT,
+                HIGH_PRIORITY_CLASS, INFINITE, NORMAL_PRIORITY_CLASS, PROCESS_INFORMATION,
+                PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SYNCHRONIZE, PROCESS_TERMINATE,
+                STARTF_USESHOWWINDOW, STARTUPINFOW,
+            },
+        },
+        UI::WindowsAndMessaging::{
+            MessageBoxA, IDYES, MB_ICONWARNING, MB_OK, MB_YESNO, SHOW_WINDOW_CMD, SW_HIDE,
+            SW_SHOWMINNOACTIVE,
+        },
+    },
+};
+
+use crate::{
+    config::ProcessPriority,
+    logging::{log_file_path, report_info_event},
+    registry::{read_registry_dword, read_registry_string},
+    Context, GameType, APP_NAME, LOCK_FILE,
+};
+
+const FF7_STEAM_APP_ID: u32 = 39140;
+const FF8_STEAM_APP_ID: u32 = 39150;
+const EXISTING_INSTANCE_EXIT_TIMEOUT_MS: u32 = 5000;
+const SPAWN_ATTEMPTS: u32 = 3;
+const SPAWN_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const CRASH_REPORT_DIR: &str = "crash_report";
+const DEBUGGER_ATTACH_TIMEOUT: Duration = Duration::from_secs(30);
+// FFNx writes its log and the dump of a game crash next to the game executable
+const GAME_CRASH_FILES: [&str; 2] = ["FFNx.log", "crash.dmp"];
+
+pub enum GameProcess {
+    Child(Child),
+    /// Process created with a show window state or suspended for a debugger
+    Handle {
+        process: HANDLE,
+        process_id: u32,
+    },
+}
+
+impl GameProcess {
+    pub fn id(&self) -> u32 {
+        match self {
+            GameProcess::Child(child) => child.id(),
+            GameProcess::Handle { process_id, .. } => *process_id,
+        }
+    }
+
+    pub fn handle(&self) -> HANDLE {
+        match self {
+            GameProcess::Child(child) => HANDLE(child.as_raw_handle()),
+            GameProcess::Handle { process, .. } => *process,
+        }
+    }
+
+    pub fn kill(&mut self) -> Result<()> {
+        match self {
+            GameProcess::Child(child) => child.kill()?,
+            GameProcess::Handle { process, .. } => unsafe { TerminateProcess(*process, 1)? },
+        }
+        Ok(())
+    }
+
+    pub fn wait(&mut self) -> Result<u32> {
+        let exit_code = match self {
+            GameProcess::Child(child) => child.wait()?.code().unwrap_or_default() as u32,
+            GameProcess::Handle { process, .. } => unsafe {
+                WaitForSingleObject(*process, INFINITE);
+                let mut exit_code = 0u32;
+                GetExitCodeProcess(*process, &mut exit_code)?;
+                exit_code
+            },
+        };
+        Ok(exit_code)
+    }
+}
+
+impl Drop for GameProcess {
+    fn drop(&mut self) {
+        if let GameProcess::Handle { process, .. } = self {
+            unsafe {
+                _ = CloseHandle(*process);
+            }
+        }
+    }
+}
+
+pub fn spawn_game(ctx: &Context, process_path: &Path) -> Result<GameProcess> {
+    let working_dir = match &ctx.config.working_dir {
+        Some(working_dir) => PathBuf::from(working_dir),
+        None => process_path
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or(anyhow::anyhow!("Directory of process not found"))?,
+    };
+    log::info!("Game working directory: {:?}", working_dir);
+    let env = game_environment(ctx)?;
+    for (key, value) in &env {
+        log::info!("Game environment variable: {key}={value}");
+    }
+    log::info!("Game arguments: {:?}", ctx.config.game_args);
+    let game_process = spawn_with_retry(ctx, process_path, &working_dir, &env)?;
+    apply_process_settings(ctx, &game_process);
+    Ok(game_process)
+}
+
+/// Environment variables set on the game, the configured ones and the Steam app id ones with
+/// launch_via_steam
+fn game_environment(ctx: &Context) -> Result<Vec<(String, String)>> {
+    let mut env = ctx.config.env.clone();
+    if !ctx.config.launch_via_steam {
+        return Ok(env);
+    }
+    // `steam -applaunch` would start the official launcher (or this one when it replaces it), so
+    // the game is still started directly. It only gets the app id variables Steam sets on the games
+    // it starts, the game itself is not started by Steam.
+    let steam_exe = find_steam_exe()?;
+    let steam_running = read_registry_dword(
+        HKEY_CURRENT_USER,
+        "Software\\Valve\\Steam\\ActiveProcess",
+        "pid",
+    )
+    .is_ok_and(|pid| pid != 0);
+    if !steam_running {
+        log::warn!("Steam ({steam_exe}) is not running");
+    }
+    let steam_app_id = steam_app_id(ctx).to_string();
+    log::info!("Launching the game as Steam app {steam_app_id}");
+    env.push(("SteamAppId".to_string(), steam_app_id.clone()));
+    env.push(("SteamGameId".to_string(), steam_app_id));
+    Ok(env)
+}
+
+fn apply_process_settings(ctx: &Context, game_process: &GameProcess) {
+    apply_affinity_mask(ctx, game_process);
+    apply_process_priority(ctx, game_process);
+}
+
+fn apply_process_priority(ctx: &Context, game_process: &GameProcess) {
+    let Some(process_priority) = ctx.config.process_priority else {
+        return;
+    };
+    let priority_class = match process_priority {
+        ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+        ProcessPriority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::High => HIGH_PRIORITY_CLASS,
+    };
+    match unsafe { SetPriorityClass(game_process.handle(), priority_class) } {
+        Ok(_) => log::info!("Game priority set to {process_priority:?}"),
+        Err(err) => log::warn!("Game priority could not be set: {err}"),
+    }
+}
+
+fn apply_affinity_mask(ctx: &Context, game_process: &GameProcess) {
+    let Some(affinity_mask) = ctx.config.affinity_mask else {
+        return;
+    };
+    if affinity_mask == 0 {
+        log::warn!("affinity_mask is empty, leaving the game affinity untouched");
+        return;
+    }
+
+    let mut process_affinity_mask = 0usize;
+    let mut system_affinity_mask = 0usize;
+    let system_mask_found = unsafe {
+        GetProcessAffinityMask(
+            GetCurrentProcess(),
+            &mut process_affinity_mask,
+            &mut system_affinity_mask,
+        )
+        .is_ok()
+    };
+    let mut applied_mask = affinity_mask as usize;
+    if system_mask_found && applied_mask & !system_affinity_mask != 0 {
+        log::warn!(
+            "affinity_mask 0x{affinity_mask:x} exceeds the available cores (0x{system_affinity_mask:x}), ignoring the missing ones"
+        );
+        applied_mask &= system_affinity_mask;
+        if applied_mask == 0 {
+            return;
+        }
+    }
+    match unsafe { SetProcessAffinityMask(game_process.handle(), applied_mask) } {
+        Ok(_) => log::info!("Game affinity mask set to 0x{applied_mask:x}"),
+        Err(err) => log::warn!("Game affinity mask could not be set: {err}"),
+    }
+}
+
+fn spawn_with_retry(
+    ctx: &Context,
+    process_path: &Path,
+    working_dir: &Path,
+    env: &[(String, String)],
+) -> Result<GameProcess> {
+    let show_window = start_window_state(ctx);
+    let mut attempt = 1;
+    loop {
+        let spawn_result = match show_window.is_some() || ctx.config.debugger.is_some() {
+            true => create_game_process(ctx, process_path, working_dir, env, show_window),
+            false => Command::new(process_path)
+                .current_dir(working_dir)
+                .args(&ctx.config.game_args)
+                .envs(env.iter().map(|(key, value)| (key, value)))
+                .spawn()
+                .map(GameProcess::Child)
+                .map_err(anyhow::Error::from),
+        };
+        match spawn_result {
+            Ok(game_process) => return Ok(game_process),
+            Err(err) if attempt < SPAWN_ATTEMPTS && is_transient_spawn_error(&err) => {
+                log::warn!(
+                    "Spawning {:?} failed (attempt {attempt}/{SPAWN_ATTEMPTS}): {err}, retrying",
+                    process_path
+                );
+                std::thread::sleep(SPAWN_RETRY_BACKOFF * attempt);
+                attempt += 1;
+            }
+            Err(err) if attempt > 1 => {
+                return Err(anyhow::anyhow!(
+                    "Spawning {:?} failed after {} attempts: {}",
+                    process_path,
+                    attempt,
+                    err
+                ))
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "Spawning {:?} failed: {}",
+                    process_path,
+                    err
+                ))
+            }
+        }
+    }
+}
+
+/// An antivirus scan or a Steam file verification only locks the executable for a moment, other
+/// spawn errors do not go away by retrying
+fn is_transient_spawn_error(err: &anyhow::Error) -> bool {
+    let is_error = |win32_error: WIN32_ERROR| {
+        let io_error_code = err
+            .downcast_ref::<std::io::Error>()
+            .and_then(|io_error| io_error.raw_os_error());
+        let windows_error_code = err
+            .downcast_ref::<windows::core::Error>()
+            .map(|windows_error| windows_error.code());
+        io_error_code == Some(win32_error.0 as i32)
+            || windows_error_code == Some(HRESULT::from_win32(win32_error.0))
+    };
+    is_error(ERROR_SHARING_VIOLATION) || is_error(ERROR_ACCESS_DENIED)
+}
+
+fn start_window_state(ctx: &Context) -> Option<SHOW_WINDOW_CMD> {
+    if ctx.config.start_hidden {
+        Some(SW_HIDE)
+    } else if ctx.config.start_minimized {
+        Some(SW_SHOWMINNOACTIVE)
+    } else {
+        None
+    }
+}
+
+/// Command::spawn can neither set the initial window state nor start the game suspended for a
+/// debugger, CreateProcessW is called directly instead
+fn create_game_process(
+    ctx: &Context,
+    process_path: &Path,
+    working_dir: &Path,
+    env: &[(String, String)],
+    show_window: Option<SHOW_WINDOW_CMD>,
+) -> Result<GameProcess> {
+    let mut command_line = std::iter::once(process_path.to_string_lossy().to_string())
+        .chain(ctx.config.game_args.iter().cloned())
+        .map(|arg| quote_arg(&arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect::<Vec<u16>>();
+    let environment = environment_block(env);
+    let working_dir = working_dir
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect::<Vec<u16>>();
+    let mut startup_info = STARTUPINFOW {
+        cb: std::mem::size_of::<STARTUPINFOW>() as u32,
+        ..Default::default()
+    };
+    if let Some(show_window) = show_window {
+        log::info!("Starting the game with show window state {}", show_window.0);
+        startup_info.dwFlags = STARTF_USESHOWWINDOW;
+        startup_info.wShowWindow = show_window.0 as u16;
+    }
+    let mut creation_flags = CREATE_UNICODE_ENVIRONMENT;
+    if ctx.config.debugger.is_some() {
+        creation_flags |= CREATE_SUSPENDED;
+    }
+    let mut process_info = PROCESS_INFORMATION::default();
+    unsafe {
+        CreateProcessW(
+            PCWSTR::null(),
+            PWSTR(command_line.as_mut_ptr()),
+            None,
+            None,
+            BOOL::from(false),
+            creation_flags,
+            Some(environment.as_ptr() as _),
+            PCWSTR(working_dir.as_ptr()),
+            &startup_info,
+            &mut process_info,
+        )?;
+    }
+    let mut game_process = GameProcess::Handle {
+        process: process_info.hProcess,
+        process_id: process_info.dwProcessId,
+    };
+    if let Some(debugger) = &ctx.config.debugger {
+        if let Err(err) = attach_debugger(debugger, &game_process) {
+            _ = game_process.kill();
+            unsafe {
+                _ = CloseHandle(process_info.hThread);
+            }
+            return Err(err);
+        }
+        unsafe {
+            ResumeThread(process_info.hThread);
+        }
+    }
+    unsafe {
+        _ = CloseHandle(process_info.hThread);
+    }
+    Ok(game_process)
+}
+
+/// Starts the debugger on the suspended game and waits for it to attach before the game runs its
+/// first instruction
+fn attach_debugger(debugger: &str, game_process: &GameProcess) -> Result<()> {
+    let process_id = game_process.id();
+    log::info!("Attaching {debugger} to the game (process_id: {process_id})");
+    // x64dbg and WinDbg both attach to the process id given with -p
+    Command::new(debugger)
+        .arg("-p")
+        .arg(process_id.to_string())
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("Debugger {} could not be started: {}", debugger, err))?;
+    let start = Instant::now();
+    while start.elapsed() < DEBUGGER_ATTACH_TIMEOUT {
+        let mut debugger_present = BOOL::from(false);
+        let checked =
+            unsafe { CheckRemoteDebuggerPresent(game_process.handle(), &mut debugger_present) };
+        if checked.is_ok() && debugger_present.as_bool() {
+            log::info!("Debugger attached, resuming the game");
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    log::warn!(
+        "{debugger} did not attach within {} seconds, resuming the game anyway",
+        DEBUGGER_ATTACH_TIMEOUT.as_secs()
+    );
+    Ok(())
+}
+
+/// Quotes an argument the way the game C runtime splits its command line
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Launcher environment with the configured variables on top, as a sequence of null terminated
+/// KEY=VALUE strings ended by an empty one
+fn environment_block(env: &[(String, String)]) -> Vec<u16> {
+    // Windows variable names are case insensitive
+    let mut variables = std::env::vars_os()
+        .map(|(key, value)| {
+            let key = key.to_string_lossy().to_string();
+            (
+                key.to_uppercase(),
+                (key, value.to_string_lossy().to_string()),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    for (key, value) in env {
+        variables.insert(key.to_uppercase(), (key.clone(), value.clone()));
+    }
+    let mut block = Vec::new();
+    for (key, value) in variables.values() {
+        block.extend(format!("{key}={value}").encode_utf16());
+        block.push(0);
+    }
+    block.push(0);
+    block
+}
+
+pub fn steam_app_id(ctx: &Context) -> u32 {
+    ctx.config.steam_app_id.unwrap_or(match ctx.game_to_launch {
+        GameType::FF7(_) => FF7_STEAM_APP_ID,
+        GameType::FF8 => FF8_STEAM_APP_ID,
+    })
+}
+
+/// Returns false when another launcher instance already launches the same game. The mutex is
+/// kept for the launcher lifetime and released by Windows when it exits.
+pub fn acquire_launcher_instance(ctx: &Context) -> Result<bool> {
+    let game_name = match ctx.game_to_launch {
+        GameType::FF7(_) => "ff7",
+        GameType::FF8 => "ff8",
+    };
+    let mutex_name = CString::new(format!("Local\\{APP_NAME}_{game_name}"))?;
+    let already_exists = unsafe {
+        CreateMutexA(None, BOOL::from(true), PCSTR(mutex_name.as_ptr() as _))?;
+        GetLastError() == ERROR_ALREADY_EXISTS
+    };
+    if !already_exists {
+        return Ok(true);
+    }
+
+    log::warn!("Another {APP_NAME} instance is already launching {game_name}");
+    let message = CString::new(format!(
+        "Another {APP_NAME} instance is already launching {}. Only one launcher per game can run at a time.",
+        game_name.to_uppercase()
+    ))?;
+    unsafe {
+        _ = MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            s!("Warning"),
+            MB_ICONWARNING | MB_OK,
+        );
+    }
+    Ok(false)
+}
+
+/// Removes the lock file when dropped, a crashed launcher leaves it behind
+pub struct LauncherLock;
+
+impl Drop for LauncherLock {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(LOCK_FILE) {
+            log::warn!("Removing {LOCK_FILE} failed: {err}");
+        }
+    }
+}
+
+pub fn write_launcher_lock() -> Result<LauncherLock> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    std::fs::write(
+        LOCK_FILE,
+        format!("{}\n{}\n", std::process::id(), timestamp),
+    )?;
+    Ok(LauncherLock)
+}
+
+/// Returns true when a previous session left its lock file behind and its launcher is not running
+pub fn previous_session_unclean() -> bool {
+    let Ok(lock) = std::fs::read_to_string(LOCK_FILE) else {
+        return false;
+    };
+    let mut lines = lock.lines();
+    let process_id = lines
+        .next()
+        .and_then(|line| line.trim().parse::<u32>().ok());
+    let timestamp = lines.next().unwrap_or("unknown").trim();
+    if let Some(process_id) = process_id {
+        if is_process_running(process_id) {
+            log::info!("{LOCK_FILE} belongs to the running launcher (process_id: {process_id})");
+            return false;
+        }
+    }
+    log::warn!(
+        "Previous session (process_id: {:?}, started at {timestamp}) ended uncleanly, {LOCK_FILE} was left behind",
+        process_id
+    );
+    true
+}
+
+pub fn is_process_running(process_id: u32) -> bool {
+    unsafe {
+        let Ok(process) = OpenProcess(
+            PROCESS_QUERY_LIMITED_INFORMATION,
+            BOOL::from(false),
+            process_id,
+        ) else {
+            return false;
+        };
+        let mut exit_code = 0u32;
+        let running = GetExitCodeProcess(process, &mut exit_code).is_ok()
+            && exit_code == STILL_ACTIVE.0 as u32;
+        _ = CloseHandle(process);
+        running
+    }
+}
+
+/// Returns false when the user chooses to abort because the game to launch is already running,
+/// the other game does not conflict with it
+pub fn check_existing_instance(ctx: &Context, process_path: &Path) -> Result<bool> {
+    let process_name = process_path
+        .file_name()
+        .ok_or(anyhow::anyhow!("Filename of process not found"))?
+        .to_string_lossy()
+        .to_lowercase();
+    let running_process = find_process(|exe_file| exe_file.to_lowercase() == process_name)?;
+    let Some((exe_file, process_id)) = running_process else {
+        return Ok(true);
+    };
+
+    if ctx.config.kill_existing_instance {
+        log::warn!("Terminating the running {exe_file} instance (process_id: {process_id})");
+        unsafe {
+            let process = OpenProcess(
+                PROCESS_TERMINATE | PROCESS_SYNCHRONIZE,
+                BOOL::from(false),
+                process_id,
+            )?;
+            let terminate_result = TerminateProcess(process, 1);
+            if terminate_result.is_ok() {
+                WaitForSingleObject(process, EXISTING_INSTANCE_EXIT_TIMEOUT_MS);
+            }
+            _ = CloseHandle(process);
+            terminate_result?;
+        }
+        return Ok(true);
+    }
+
+    log::warn!("{exe_file} is already running (process_id: {process_id})");
+    let message = CString::new(format!(
+        "{exe_file} is already running (process_id: {process_id}). Launching again may conflict with it, abort the launch?"
+    ))?;
+    let answer = unsafe {
+        MessageBoxA(
+            None,
+            PCSTR(message.as_ptr() as _),
+            s!("Warning"),
+            MB_ICONWARNING | MB_YESNO,
+        )
+    };
+    Ok(answer != IDYES)
+}
+
+pub fn report_game_exit(ctx: &Context, process_path: &Path, exit_code: u32) {
+    if exit_code == 0 {
+        log::info!("Game exited with code 0");
+        report_info_event("Game exited with code 0");
+        return;
+    }
+
+    log::error!("Game exited abnormally with code {exit_code} (0x{exit_code:x})");
+    let crash_report_dir = match ctx.config.collect_crash_report {
+        true => collect_crash_report(process_path)
+            .inspect(|report_dir| log::info!("Crash report saved in {:?}", report_dir))
+            .inspect_err(|err| log::warn!("Crash report could not be saved: {:?}", err))
+            .ok(),
+        false => None,
+    };
+    if ctx.config.warn_on_crash {
+        let details = match &crash_report_dir {
+            Some(report_dir) => format!("A crash report was saved in {}", report_dir.display()),
+            None => "Check the log file for more info".to_string(),
+        };
+        let Ok(message) = CString::new(format!(
+            "The game exited abnormally with code {exit_code} (0x{exit_code:x}). {details}"
+        )) else {
+            return;
+        };
+        unsafe {
+            _ = MessageBoxA(
+                None,
+                PCSTR(message.as_ptr() as _),
+                s!("Warning"),
+                MB_ICONWARNING | MB_OK,
+            );
+        }
+    }
+}
+
+/// Copies the launcher log and the logs and dump the game left next to its executable into a
+/// timestamped directory, to share as a single bug report
+fn collect_crash_report(process_path: &Path) -> Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let report_dir = Path::new(CRASH_REPORT_DIR).join(timestamp.to_string());
+    std::fs::create_dir_all(&report_dir)?;
+    let game_dir = process_path.parent().unwrap_or(Path::new("."));
+    let report_files = GAME_CRASH_FILES
+        .iter()
+        .map(|file_name| game_dir.join(file_name))
+        .chain(log_file_path());
+    for report_file in report_files {
+        let Some(file_name) = report_file.file_name() else {
+            continue;
+        };
+        if !report_file.is_file() {
+            continue;
+        }
+        if let Err(err) = std::fs::copy(&report_file, report_dir.join(file_name)) {
+            log::warn!(
+                "Copying {:?} into the crash report failed: {err}",
+                report_file
+            );
+        }
+    }
+    Ok(report_dir)
+}
+
+pub fn run_launch_hook(ctx: &Context, hook_name: &str, command: Option<&String>) -> Result<()> {
+    let Some(command) = command else {
+        return Ok(());
+    };
+
+    log::info!("Running {hook_name} command: {command}");
+    let hook_error = match Command::new("cmd").args(["/C", command]).status() {
+        Ok(status) if status.success() => {
+            log::info!("{hook_name} command finished ({status})");
+            return Ok(());
+        }
+        Ok(status) => anyhow::anyhow!("{} command failed ({})", hook_name, status),
+        Err(err) => anyhow::anyhow!("{} command could not be run: {}", hook_name, err),
+    };
+    if ctx.config.continue_on_hook_error {
+        log::warn!("{:?}, continuing anyway", hook_error);
+        return Ok(());
+    }
+    Err(hook_error)
+}
+
+/// Runs on_launch_command without waiting for it, so the game handshake is not delayed
+pub fn run_on_launch_command(ctx: &Context, process_id: u32) {
+    let Some(command) = &ctx.config.on_launch_command else {
+        return;
+    };
+    let command = command.replace("{pid}", &process_id.to_string());
+    log::info!("Running on_launch command: {command}");
+    std::thread::spawn(
+        move || match Command::new("cmd").args(["/C", &command]).status() {
+            Ok(status) if status.success() => log::info!("on_launch command finished ({status})"),
+            Ok(status) => log::warn!("on_launch command failed ({status})"),
+            Err(err) => log::warn!("on_launch command could not be run: {err}"),
+        },
+    );
+}
+
+fn find_steam_exe() -> Result<String> {
+    let steam_exe = read_registry_string(HKEY_CURRENT_USER, "Software\\Valve\\Steam", "SteamExe")
+        .map_err(|err| anyhow::anyhow!("Steam installation not found: {}", err))?;
+    if !matches!(std::fs::exists(&steam_exe), Ok(true)) {
+        return Err(anyhow::anyhow!("Steam executable not found: {}", steam_exe));
+    }
+    Ok(steam_exe)
+}
+
+fn find_process(predicate: impl Fn(&str) -> bool) -> Result<Option<(String, u32)>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)? };
+    let mut process_entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut process = None;
+    let mut has_process_entry = unsafe { Process32FirstW(snapshot, &mut process_entry) }.is_ok();
+    while has_process_entry {
+        let exe_file_len = process_entry
+            .szExeFile
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(process_entry.szExeFile.len());
+        let exe_file = String::from_utf16_lossy(&process_entry.szExeFile[..exe_file_len]);
+        if predicate(&exe_file) {
+            process = Some((exe_file, process_entry.th32ProcessID));
+            break;
+        }
+        has_process_entry = unsafe { Process32NextW(snapshot, &mut process_entry) }.is_ok();
+    }
+    unsafe {
+        _ = CloseHandle(snapshot);
+    }
+    Ok(process)
+}
@@ -0,0 +1,52 @@
+use std::ffi::{CStr, CString};
+
+use anyhow::Result;
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::MAX_PATH,
+        System::Registry::{RegGetValueA, HKEY, RRF_RT_REG_DWORD, RRF_RT_REG_SZ},
+    },
+};
+
+pub fn read_registry_string(hkey: HKEY, subkey: &str, value: &str) -> Result<String> {
+    let subkey_name = CString::new(subkey)?;
+    let value_name = CString::new(value)?;
+    let mut data = [0u8; MAX_PATH as usize];
+    let mut data_len = data.len() as u32;
+    unsafe {
+        RegGetValueA(
+            hkey,
+            PCSTR(subkey_name.as_ptr() as _),
+            PCSTR(value_name.as_ptr() as _),
+            RRF_RT_REG_SZ,
+            None,
+            Some(data.as_mut_ptr() as _),
+            Some(&mut data_len as *mut _),
+        )
+        .ok()?;
+    }
+    Ok(CStr::from_bytes_until_nul(&data)?
+        .to_string_lossy()
+        .to_string())
+}
+
+pub fn read_registry_dword(hkey: HKEY, subkey: &str, value: &str) -> Result<u32> {
+    let subkey_name = CString::new(subkey)?;
+    let value_name = CString::new(value)?;
+    let mut data = 0u32;
+    let mut data_len = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        RegGetValueA(
+            hkey,
+            PCSTR(subkey_name.as_ptr() as _),
+            PCSTR(value_name.as_ptr() as _),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut data as *mut u32 as _),
+            Some(&mut data_len as *mut _),
+        )
+        .ok()?;
+    }
+    Ok(data)
+}
@@ -0,0 +1,163 @@
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicIsize, AtomicU32, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::Result;
+use windows::{
+    core::{s, PCSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, WPARAM},
+        System::{SystemServices::SS_CENTER, Threading::GetCurrentThreadId},
+        UI::WindowsAndMessaging::{
+            CreateWindowExA, DestroyWindow, DispatchMessageA, GetMessageA, GetSystemMetrics,
+            PostThreadMessageA, SetWindowTextA, TranslateMessage, MSG, SM_CXSCREEN, SM_CYSCREEN,
+            WINDOW_STYLE, WM_QUIT, WS_BORDER, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+            WS_VISIBLE,
+        },
+    },
+};
+
+use crate::window::find_game_window;
+
+const SPLASH_WIDTH: i32 = 320;
+const SPLASH_HEIGHT: i32 = 60;
+const SPLASH_GAME_WINDOW_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The launch steps update the status without a handle to the splash window
+static SPLASH_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+static SPLASH_WINDOW: AtomicIsize = AtomicIsize::new(0);
+
+/// Closes the status window and stops its thread when dropped
+pub struct SplashWindow {
+    thread_id: u32,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SplashWindow {
+    fn drop(&mut self) {
+        close_splash(self.thread_id);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Splash window thread join failed!");
+            }
+        }
+    }
+}
+
+/// Shows the status window when show_splash is enabled, the launch goes on without it when it
+/// cannot be shown
+pub fn show_splash(show_splash: bool) -> Option<SplashWindow> {
+    if !show_splash {
+        return None;
+    }
+    create_splash_window()
+        .inspect_err(|err| log::warn!("Splash window could not be shown: {:?}", err))
+        .ok()
+}
+
+/// Updates the status window text, nothing happens when it is not shown
+pub fn set_splash_status(status: &str) {
+    let hwnd = SPLASH_WINDOW.load(Ordering::Relaxed);
+    if hwnd == 0 {
+        return;
+    }
+    let Ok(status) = CString::new(status) else {
+        return;
+    };
+    unsafe {
+        _ = SetWindowTextA(HWND(hwnd as _), PCSTR(status.as_ptr() as _));
+    }
+}
+
+/// Closes the status window once the game shows its own one
+pub fn close_splash_on_game_window(process_id: u32) {
+    let thread_id = SPLASH_THREAD_ID.load(Ordering::Relaxed);
+    if thread_id == 0 {
+        return;
+    }
+    std::thread::spawn(move || {
+        if find_game_window(process_id, SPLASH_GAME_WINDOW_TIMEOUT).is_none() {
+            log::info!("Game window not found, the splash window stays until the game exits");
+            return;
+        }
+        close_splash(thread_id);
+    });
+}
+
+fn close_splash(thread_id: u32) {
+    unsafe {
+        _ = PostThreadMessageA(thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+    }
+}
+
+fn create_splash_window() -> Result<SplashWindow> {
+    let (thread_id_tx, thread_id_rx) = mpsc::channel::<Result<u32>>();
+    // The window needs a thread pumping its messages while the launcher works
+    let thread = std::thread::spawn(move || {
+        let hwnd = match create_status_window() {
+            Ok(hwnd) => {
+                let thread_id = unsafe { GetCurrentThreadId() };
+                SPLASH_THREAD_ID.store(thread_id, Ordering::Relaxed);
+                SPLASH_WINDOW.store(hwnd.0 as isize, Ordering::Relaxed);
+                _ = thread_id_tx.send(Ok(thread_id));
+                hwnd
+            }
+            Err(err) => {
+                _ = thread_id_tx.send(Err(err));
+                return;
+            }
+        };
+        let mut message = MSG::default();
+        // GetMessageA returns 0 once WM_QUIT is received and -1 on errors
+        while unsafe { GetMessageA(&mut message, None, 0, 0) }.0 > 0 {
+            unsafe {
+                _ = TranslateMessage(&message);
+                DispatchMessageA(&message);
+            }
+        }
+        SPLASH_WINDOW.store(0, Ordering::Relaxed);
+        SPLASH_THREAD_ID.store(0, Ordering::Relaxed);
+        unsafe {
+            _ = DestroyWindow(hwnd);
+        }
+    });
+    match thread_id_rx.recv()? {
+        Ok(thread_id) => Ok(SplashWindow {
+            thread_id,
+            thread: Some(thread),
+        }),
+        Err(err) => {
+            _ = thread.join();
+            Err(err)
+        }
+    }
+}
+
+fn create_status_window() -> Result<HWND> {
+    unsafe {
+        let x = (GetSystemMetrics(SM_CXSCREEN) - SPLASH_WIDTH) / 2;
+        let y = (GetSystemMetrics(SM_CYSCREEN) - SPLASH_HEIGHT) / 2;
+        // A static control is enough to show a line of text, no window class is needed
+        let hwnd = CreateWindowExA(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+            s!("STATIC"),
+            s!("Starting..."),
+            WS_POPUP | WS_BORDER | WS_VISIBLE | WINDOW_STYLE(SS_CENTER.0),
+            x,
+            y,
+            SPLASH_WIDTH,
+            SPLASH_HEIGHT,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        Ok(hwnd)
+    }
+}
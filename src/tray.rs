@@ -0,0 +1,258 @@
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::Result;
+use windows::{
+    core::{s, PCSTR},
+    Win32::{
+        Foundation::{CloseHandle, BOOL, HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM},
+        System::{
+            LibraryLoader::GetModuleHandleA,
+            Threading::{GetCurrentThreadId, OpenProcess, TerminateProcess, PROCESS_TERMINATE},
+        },
+        UI::{
+            Shell::{
+                ShellExecuteA, Shell_NotifyIconA, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD,
+                NIM_DELETE, NOTIFYICONDATAA,
+            },
+            WindowsAndMessaging::{
+                AppendMenuA, CreatePopupMenu, CreateWindowExA, DefWindowProcA, DestroyMenu,
+                DestroyWindow, DispatchMessageA, GetCursorPos, GetMessageA, LoadIconW,
+                PostThreadMessageA, RegisterClassA, SetForegroundWindow, TrackPopupMenu,
+                TranslateMessage, IDI_APPLICATION, MF_STRING, MSG, SW_SHOWNORMAL, TPM_RETURNCMD,
+                TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WM_QUIT, WM_RBUTTONUP,
+                WNDCLASSA,
+            },
+        },
+    },
+};
+
+use crate::{logging::log_file_path, Context, APP_NAME};
+
+const TRAY_ICON_ID: u32 = 1;
+const TRAY_ICON_MESSAGE: u32 = WM_APP + 1;
+const MENU_KILL_GAME: usize = 1;
+const MENU_OPEN_LOG: usize = 2;
+
+// The window procedure has no other way to know the game it acts on
+static GAME_PROCESS_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Removes the tray icon and stops its thread when dropped
+pub struct TrayIcon {
+    thread_id: u32,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for TrayIcon {
+    fn drop(&mut self) {
+        unsafe {
+            _ = PostThreadMessageA(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Tray icon thread join failed!");
+            }
+        }
+    }
+}
+
+/// Shows the tray icon while the game runs when show_tray_icon is enabled, the launch goes on
+/// without it when it cannot be shown
+pub fn show_tray_icon(ctx: &Context, process_id: u32) -> Option<TrayIcon> {
+    if !ctx.config.show_tray_icon {
+        return None;
+    }
+    create_tray_icon(process_id)
+        .inspect_err(|err| log::warn!("Tray icon could not be shown: {:?}", err))
+        .ok()
+}
+
+fn create_tray_icon(process_id: u32) -> Result<TrayIcon> {
+    GAME_PROCESS_ID.store(process_id, Ordering::Relaxed);
+    let (thread_id_tx, thread_id_rx) = mpsc::channel::<Result<u32>>();
+    // The tray window needs a thread pumping its messages while the launcher waits for the game
+    let thread = std::thread::spawn(move || {
+        let hwnd = match create_tray_window() {
+            Ok(hwnd) => {
+                _ = thread_id_tx.send(Ok(unsafe { GetCurrentThreadId() }));
+                hwnd
+            }
+            Err(err) => {
+                _ = thread_id_tx.send(Err(err));
+                return;
+            }
+        };
+        log::info!("Tray icon shown");
+        run_message_loop();
+        unsafe {
+            _ = Shell_NotifyIconA(NIM_DELETE, &tray_icon_data(hwnd));
+            _ = DestroyWindow(hwnd);
+        }
+        log::info!("Tray icon removed");
+    });
+    match thread_id_rx.recv()? {
+        Ok(thread_id) => Ok(TrayIcon {
+            thread_id,
+            thread: Some(thread),
+        }),
+        Err(err) => {
+            _ = thread.join();
+            Err(err)
+        }
+    }
+}
+
+fn create_tray_window() -> Result<HWND> {
+    unsafe {
+        let instance = HINSTANCE::from(GetModuleHandleA(PCSTR::null())?);
+        let class_name = s!("FF78LauncherTray");
+        let window_class = WNDCLASSA {
+            lpfnWndProc: Some(tray_window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        if RegisterClassA(&window_class) == 0 {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        // The window is never shown, it only receives the tray icon notifications
+        let hwnd = CreateWindowExA(
+            WINDOW_EX_STYLE::default(),
+            class_name,
+            s!("FF78Launcher"),
+            WINDOW_STYLE::default(),
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            instance,
+            None,
+        )?;
+
+        let mut notify_icon_data = tray_icon_data(hwnd);
+        notify_icon_data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+        notify_icon_data.uCallbackMessage = TRAY_ICON_MESSAGE;
+        notify_icon_data.hIcon = LoadIconW(None, IDI_APPLICATION)?;
+        let tip = format!("{APP_NAME} - right click to kill the game or open the log");
+        // The last byte is kept as null terminator
+        for (tip_char, byte) in notify_icon_data.szTip[..127].iter_mut().zip(tip.bytes()) {
+            *tip_char = byte as i8;
+        }
+        if !Shell_NotifyIconA(NIM_ADD, &notify_icon_data).as_bool() {
+            _ = DestroyWindow(hwnd);
+            return Err(anyhow::anyhow!("Adding the tray icon failed"));
+        }
+        Ok(hwnd)
+    }
+}
+
+fn tray_icon_data(hwnd: HWND) -> NOTIFYICONDATAA {
+    NOTIFYICONDATAA {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAA>() as u32,
+        hWnd: hwnd,
+        uID: TRAY_ICON_ID,
+        ..Default::default()
+    }
+}
+
+fn run_message_loop() {
+    let mut message = MSG::default();
+    // GetMessageA returns 0 once WM_QUIT is received and -1 on errors
+    while unsafe { GetMessageA(&mut message, None, 0, 0) }.0 > 0 {
+        unsafe {
+            _ = TranslateMessage(&message);
+            DispatchMessageA(&message);
+        }
+    }
+}
+
+unsafe extern "system" fn tray_window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == TRAY_ICON_MESSAGE && lparam.0 as u32 == WM_RBUTTONUP {
+        show_tray_menu(hwnd);
+        return LRESULT(0);
+    }
+    DefWindowProcA(hwnd, msg, wparam, lparam)
+}
+
+fn show_tray_menu(hwnd: HWND) {
+    let command = unsafe {
+        let Ok(menu) = CreatePopupMenu() else {
+            return;
+        };
+        _ = AppendMenuA(menu, MF_STRING, MENU_KILL_GAME, s!("Kill game"));
+        _ = AppendMenuA(menu, MF_STRING, MENU_OPEN_LOG, s!("Open log"));
+        let mut cursor = POINT::default();
+        _ = GetCursorPos(&mut cursor);
+        // The menu does not close when clicking elsewhere unless its window is in the foreground
+        _ = SetForegroundWindow(hwnd);
+        let command = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_RIGHTBUTTON,
+            cursor.x,
+            cursor.y,
+            0,
+            hwnd,
+            None,
+        );
+        _ = DestroyMenu(menu);
+        command.0 as usize
+    };
+    match command {
+        MENU_KILL_GAME => kill_game(),
+        MENU_OPEN_LOG => open_log(),
+        _ => {}
+    }
+}
+
+fn kill_game() {
+    let process_id = GAME_PROCESS_ID.load(Ordering::Relaxed);
+    log::warn!("Terminating the game from the tray icon (process_id: {process_id})");
+    unsafe {
+        match OpenProcess(PROCESS_TERMINATE, BOOL::from(false), process_id) {
+            Ok(process) => {
+                if let Err(err) = TerminateProcess(process, 1) {
+                    log::error!("Terminating the game failed: {err}");
+                }
+                _ = CloseHandle(process);
+            }
+            Err(err) => log::error!("Opening the game process failed: {err}"),
+        }
+    }
+}
+
+fn open_log() {
+    let Some(log_path) = log_file_path() else {
+        log::warn!("No log file to open");
+        return;
+    };
+    let Ok(log_file) = CString::new(log_path.to_string_lossy().to_string()) else {
+        return;
+    };
+    let result = unsafe {
+        ShellExecuteA(
+            None,
+            s!("open"),
+            PCSTR(log_file.as_ptr() as _),
+            PCSTR::null(),
+            PCSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values up to 32 are error codes
+    if result.0 as usize <= 32 {
+        log::warn!("Opening {:?} failed (code {})", log_path, result.0 as usize);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    ffi::CString,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use windows::{
+    core::{s, PCSTR},
+    Win32::{
+        Foundation::{BOOL, HWND, LPARAM},
+        UI::WindowsAndMessaging::{
+            EnumWindows, GetWindowLongA, GetWindowThreadProcessId, IsWindowVisible, MessageBoxA,
+            SetWindowLongA, SetWindowPos, GWL_STYLE, MB_ICONWARNING, MB_OK, SWP_FRAMECHANGED,
+            SWP_NOACTIVATE, SWP_NOSIZE, SWP_NOZORDER, WS_CAPTION, WS_SYSMENU, WS_THICKFRAME,
+        },
+    },
+};
+
+use crate::{process::is_process_running, Context};
+
+const GAME_WINDOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct FindWindowState {
+    process_id: u32,
+    hwnd: Option<HWND>,
+}
+
+pub fn find_game_window(process_id: u32, timeout: Duration) -> Option<HWND> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let mut state = FindWindowState {
+            process_id,
+            hwnd: None,
+        };
+        unsafe {
+            // EnumWindows reports an error when the enumeration gets stopped early
+            _ = EnumWindows(
+                Some(find_process_window_proc),
+                LPARAM(&mut state as *mut _ as isize),
+            );
+        }
+        if state.hwnd.is_some() {
+            return state.hwnd;
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    None
+}
+
+/// Warns when the game keeps running without ever opening a window, which usually means the
+/// graphics driver or FFNx failed to start
+pub fn start_window_watchdog(ctx: &Context, process_id: u32) {
+    if ctx.config.window_watchdog_secs == 0 {
+        return;
+    }
+    let timeout = Duration::from_secs(ctx.config.window_watchdog_secs as u64);
+    let show_dialog = ctx.config.window_watchdog_dialog;
+    std::thread::spawn(move || {
+        if find_game_window(process_id, timeout).is_some() {
+            log::info!("Game window found, window watchdog stopped");
+            return;
+        }
+        if !is_process_running(process_id) {
+            return;
+        }
+        log::warn!(
+            "Game (process_id: {process_id}) did not open a window within {} seconds, check the graphics and FFNx settings",
+            timeout.as_secs()
+        );
+        if !show_dialog {
+            return;
+        }
+        let Ok(message) = CString::new(format!(
+            "The game is running but did not open a window within {} seconds. This usually is a graphics driver or FFNx problem, check the log file for more info",
+            timeout.as_secs()
+        )) else {
+            return;
+        };
+        unsafe {
+            _ = MessageBoxA(
+                None,
+                PCSTR(message.as_ptr() as _),
+                s!("Warning"),
+                MB_ICONWARNING | MB_OK,
+            );
+        }
+    });
+}
+
+pub fn move_game_window(process_id: u32, x: i32, y: i32) -> Result<()> {
+    let Some(hwnd) = find_game_window(process_id, GAME_WINDOW_TIMEOUT) else {
+        return Err(anyhow::anyhow!(
+            "Game window not found within {} seconds",
+            GAME_WINDOW_TIMEOUT.as_secs()
+        ));
+    };
+    unsafe {
+        SetWindowPos(
+            hwnd,
+            None,
+            x,
+            y,
+            0,
+            0,
+            SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+        )?;
+    }
+    log::info!("Game window moved to ({x}, {y})");
+    Ok(())
+}
+
+pub fn make_game_window_borderless(
+    process_id: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let Some(hwnd) = find_game_window(process_id, GAME_WINDOW_TIMEOUT) else {
+        return Err(anyhow::anyhow!(
+            "Game window not found within {} seconds",
+            GAME_WINDOW_TIMEOUT.as_secs()
+        ));
+    };
+    unsafe {
+        // The Ptr variants are not exported on 32-bit Windows, the style fits in 32 bits anyway
+        let style = GetWindowLongA(hwnd, GWL_STYLE) as u32;
+        SetWindowLongA(
+            hwnd,
+            GWL_STYLE,
+            (style & !(WS_CAPTION.0 | WS_THICKFRAME.0 | WS_SYSMENU.0)) as i32,
+        );
+        SetWindowPos(
+            hwnd,
+            None,
+            x,
+            y,
+            width as i32,
+            height as i32,
+            SWP_FRAMECHANGED | SWP_NOZORDER | SWP_NOACTIVATE,
+        )?;
+    }
+    log::info!("Game window made borderless at ({x}, {y}) with size {width}x{height}");
+    Ok(())
+}
+
+unsafe extern "system" fn find_process_window_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let state = &mut *(lparam.0 as *mut FindWindowState);
+    let mut window_process_id = 0u32;
+    GetWindowThreadProcessId(hwnd, Some(&mut window_process_id as *mut _));
+    if window_process_id == state.process_id && IsWindowVisible(hwnd).as_bool() {
+        state.hwnd = Some(hwnd);
+        return false.into();
+    }
+    true.into()
+}